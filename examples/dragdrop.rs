@@ -0,0 +1,133 @@
+extern crate skryn;
+extern crate webrender;
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use skryn::elements::*;
+use skryn::gui::properties::Property;
+use skryn::gui::window::Manager;
+
+use webrender::api::ColorF;
+
+/*
+    What is dragged around, the card itself.
+*/
+struct Card(Arc<Mutex<Element>>);
+
+fn new_card(text: &str) -> Arc<Mutex<Element>> {
+    let label = Arc::new(Mutex::new(Label::new(text.to_owned())));
+    let card: Arc<Mutex<Element>> = label.clone();
+
+    //a card starts a drag with itself as the payload
+    let payload = card.clone();
+    label
+        .lock()
+        .unwrap()
+        .on::<on::DragStart, _>(move |_label: &mut Label, e: &DragStartEvent| {
+            e.start(DragData::new(Card(payload.clone())));
+            true
+        });
+
+    card
+}
+
+fn new_column(color: ColorF, cards: &[&str]) -> Arc<Mutex<VBox>> {
+    let column = Arc::new(Mutex::new(VBox::new()));
+    {
+        let mut col = column.lock().unwrap();
+        col.set(Property::BgColor(color));
+        for text in cards.iter() {
+            col.append(new_card(text));
+        }
+        //accepting in drag over highlights the drag image
+        col.on::<on::DragOver, _>(|_col: &mut VBox, e: &DragEvent| {
+            if e.data.get::<Card>().is_some() {
+                e.accept();
+            }
+            true
+        });
+    }
+    column
+}
+
+/*
+    A dropped card goes in front of the first card below the cursor,
+    which reorders the column or moves the card from one of the others.
+*/
+fn accept_cards(column: &Arc<Mutex<VBox>>, others: Vec<Arc<Mutex<VBox>>>) {
+    column
+        .lock()
+        .unwrap()
+        .on::<on::Drop, _>(move |col: &mut VBox, e: &DragEvent| {
+            let card = match e.data.get::<Card>() {
+                Some(card) => card.0.clone(),
+                None => return false,
+            };
+            //only this column is locked while its handler runs
+            if col.remove(&card).is_none() {
+                for other in others.iter() {
+                    if other.lock().unwrap().remove(&card).is_some() {
+                        break;
+                    }
+                }
+            }
+            let index = col
+                .get_children()
+                .iter()
+                .take_while(|c| {
+                    let b = c.lock().unwrap().get_bounds();
+                    b.y + b.h / 2.0 < e.position.y
+                })
+                .count();
+            col.insert(index, card);
+            true
+        });
+}
+
+fn main() {
+    let columns = vec![
+        new_column(
+            ColorF::new(0.9, 0.9, 1.0, 1.0),
+            &["Write the docs", "Fix the build", "Release"],
+        ),
+        new_column(ColorF::new(0.9, 1.0, 0.9, 1.0), &["Plan the sprint"]),
+        new_column(ColorF::new(1.0, 0.9, 0.9, 1.0), &[]),
+    ];
+
+    let board = Arc::new(Mutex::new(HBox::new()));
+    for (i, column) in columns.iter().enumerate() {
+        let others = columns
+            .iter()
+            .enumerate()
+            .filter(|&(j, _)| j != i)
+            .map(|(_, c)| c.clone())
+            .collect();
+        accept_cards(column, others);
+        board.lock().unwrap().append(column.clone());
+    }
+
+    //files dragged in from the desktop arrive as a Vec<PathBuf>
+    let files = Arc::new(Mutex::new(Label::new(String::from("Drop files here"))));
+    {
+        let mut f = files.lock().unwrap();
+        f.on::<on::DragOver, _>(|_label: &mut Label, e: &DragEvent| {
+            if e.data.get::<Vec<PathBuf>>().is_some() {
+                e.accept();
+            }
+            true
+        });
+        f.on::<on::Drop, _>(|label: &mut Label, e: &DragEvent| match e.data.get::<Vec<PathBuf>>() {
+            Some(paths) => {
+                let names: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+                label.set_value(names.join("\n"));
+                true
+            }
+            None => false,
+        });
+    }
+    board.lock().unwrap().append(files);
+
+    Manager::add(board, String::from("Drag and drop"), 600.0, 300.0);
+    Manager::start(60);
+}
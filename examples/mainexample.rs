@@ -2,6 +2,7 @@ extern crate skryn;
 extern crate webrender;
 
 use std::any::Any;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
@@ -9,8 +10,10 @@ use std::time::Duration;
 use skryn::data::*;
 use skryn::elements::*;
 use skryn::gui::font::FontStore;
+use skryn::gui::window::{Manager, Shortcut};
 use skryn::gui::properties::{Extent, IdGenerator, Properties, Property};
 
+use glutin::VirtualKeyCode;
 use webrender::api::{ColorF, DisplayListBuilder, RenderApi};
 
 /*
@@ -197,14 +200,15 @@ impl PersonElm {
         }
 
         //The following is a simple action taken when our button is clicked.
-        //An alert window is created.
-        alert_button.lock().unwrap().set_handler(
-            ElementEvent::Clicked,
-            EventFn::new(Arc::new(Mutex::new(move |_e: &mut Element, _d: &Any| {
+        //An alert window is created. Typed handlers get the Button itself
+        //and the details of the click.
+        alert_button
+            .lock()
+            .unwrap()
+            .on::<on::Clicked, _>(move |_button: &mut Button, _click: &ClickEvent| {
                 Alert::show("This is an Alert Box".to_owned(), "Alert".to_owned());
                 true
-            }))),
-        );
+            });
 
         // make sure you sae the observer id for age
         // so that we can remove the listener when
@@ -278,7 +282,10 @@ impl Element for PersonElm {
         will be that of vbox and alert_button.
 
         There are certain events where ext_ids are empty, but passing the
-        event to the children is still required for e.g., SetFocus
+        event to the children is still required for e.g., SetFocus.
+        Button, key and char events are dispatched by the window along
+        get_children. Without get_children the VBox gets the tags of the
+        elements inside and passes the events on itself.
     */
     fn on_primitive_event(&mut self, ext_ids: &[(u64, u16)], e: PrimitiveEvent) -> bool {
         match self.vbox.lock() {
@@ -302,6 +309,15 @@ impl Element for PersonElm {
     fn as_any_mut(&mut self) -> &mut Any {
         self
     }
+
+    /*
+        Exposing the VBox lets the window find the elements inside
+        PersonElm, so clicks and key presses reach them directly.
+    */
+    fn get_children(&self) -> Vec<Arc<Mutex<Element>>> {
+        let vbox: Arc<Mutex<Element>> = self.vbox.clone();
+        vec![vbox]
+    }
 }
 
 impl Drop for PersonElm {
@@ -324,7 +340,7 @@ struct Alert;
 impl Alert {
     fn show(message: String, heading: String) {
         let msg_box = TextBox::new(message);
-        skryn::gui::window::Manager::add(Arc::new(Mutex::new(msg_box)), heading, 400.0, 100.0);
+        Manager::add(Arc::new(Mutex::new(msg_box)), heading, 400.0, 100.0);
     }
 }
 
@@ -338,19 +354,52 @@ fn main() {
     let tmp_person = person.clone();
 
     //create an Instance of PersonElm and add it to the window manager.
-    let form = PersonElm::new(person);
-    skryn::gui::window::Manager::add(
-        Arc::new(Mutex::new(form)),
-        String::from("Main window"),
-        300.0,
-        200.0,
+    let form: Arc<Mutex<Element>> = Arc::new(Mutex::new(PersonElm::new(person)));
+    Manager::add(form.clone(), String::from("Main window"), 300.0, 200.0);
+
+    //window shortcuts work whichever element has focus.
+    //F1 lists them, the way a help screen would.
+    let root = form.clone();
+    Manager::add_shortcut(&form, Shortcut::new(VirtualKeyCode::F1), "Show shortcuts", move || {
+        for (shortcut, description) in Manager::get_shortcuts(&root) {
+            println!("{:<12} {}", shortcut.to_string(), description);
+        }
+    })
+    .unwrap();
+
+    //a right click anywhere in the form opens its context menu
+    let counting = Arc::new(AtomicBool::new(true));
+    let mut menu = Menu::new();
+    let root = form.clone();
+    menu.add_item(
+        MenuItem::new("Show shortcuts")
+            .with_hint(&Shortcut::new(VirtualKeyCode::F1).to_string())
+            .on_select(move |_| {
+                for (shortcut, description) in Manager::get_shortcuts(&root) {
+                    println!("{:<12} {}", shortcut.to_string(), description);
+                }
+            }),
+    );
+    let flag = counting.clone();
+    menu.add_item(
+        MenuItem::new("Count age")
+            .with_check(true)
+            .on_select(move |checked| flag.store(checked, Ordering::SeqCst)),
     );
+    menu.add_separator();
+    let mut alerts = Menu::new();
+    alerts.add_item(MenuItem::new("Show alert").on_select(|_| {
+        Alert::show("This is an Alert Box".to_owned(), "Alert".to_owned());
+    }));
+    alerts.add_item(MenuItem::new("Show nothing").with_enabled(false));
+    menu.add_item(MenuItem::new("Alerts").with_submenu(alerts));
+    Manager::set_context_menu(&form, Some(Arc::new(Mutex::new(menu))));
 
     //spawn a worker thread to update the age
     thread::spawn(move || {
         let mut t = 0;
         loop {
-            {
+            if counting.load(Ordering::SeqCst) {
                 let mut x = tmp_person.lock().unwrap();
                 t = t + 1;
                 x.age.update(Action::Update(t / 100));
@@ -360,5 +409,5 @@ fn main() {
     });
 
     //start the window manager at 60 fps
-    skryn::gui::window::Manager::start(60);
+    Manager::start(60);
 }
@@ -1,11 +1,13 @@
 use std::any::Any;
 use std::sync::Arc;
 
+use glutin::VirtualKeyCode;
 use webrender::api::*;
 
 use crate::elements::element::*;
 use crate::gui::font;
 use crate::gui::properties;
+use crate::gui::window::Manager;
 
 pub struct Button {
     ext_id: u64,
@@ -16,6 +18,7 @@ pub struct Button {
     event_handlers: EventHandlers,
     drawn: u8,
     hovering: bool,
+    focus: bool,
     enabled: bool,
 }
 
@@ -31,7 +34,8 @@ impl Button {
             .set(properties::Property::Color(ColorF::new(0.2, 0.2, 0.2, 1.0)))
             .set(properties::Property::HoverBgColor(ColorF::new(
                 0.6, 0.7, 0.7, 1.0,
-            )));
+            )))
+            .set(properties::Property::Cursor(properties::Cursor::Pointer));
         Button {
             ext_id: 0,
             value: s.chars().collect(),
@@ -41,6 +45,7 @@ impl Button {
             event_handlers: EventHandlers::new(),
             drawn: 0,
             hovering: false,
+            focus: false,
             enabled: true,
         }
     }
@@ -147,12 +152,15 @@ impl Element for Button {
         let size = self.props.get_size() as f32;
         let family = self.props.get_family();
         let text_align = self.props.get_text_align();
+        let word_break = self.props.get_word_break();
+        let features = self.props.get_font_features();
+        let language = self.props.get_language();
         let top = self.props.get_top();
         let right = self.props.get_right();
         let bottom = self.props.get_bottom();
         let left = self.props.get_left();
 
-        if self.hovering && self.enabled {
+        if (self.hovering || self.focus) && self.enabled {
             color = self.props.get_hover_color();
             bgcolor = self.props.get_hover_bg_color();
         }
@@ -226,8 +234,8 @@ impl Element for Button {
             None => size,
         };
 
-        let mut paras = font::Paragraphs::from_chars(&self.value);
-        paras.shape(
+        let mut paras = font_store.get_paragraphs(
+            &self.value,
             calc_x,
             text_y,
             calc_w,
@@ -236,6 +244,9 @@ impl Element for Button {
             baseline,
             &family,
             &text_align,
+            &word_break,
+            &features,
+            &language,
         );
 
         self.text_bounds = paras.get_extent();
@@ -291,7 +302,7 @@ impl Element for Button {
         let mut handled = false;
 
         match e {
-            PrimitiveEvent::Button(_p, b, s, m) => {
+            PrimitiveEvent::Button(p, b, s, m, clicks) => {
                 self.drawn = 0;
                 if ext_ids.len() == 1
                     && ext_ids[0].0 == self.ext_id
@@ -299,19 +310,52 @@ impl Element for Button {
                     && s == properties::ButtonState::Released
                     && self.enabled
                 {
-                    handled = self.exec_handler(ElementEvent::Clicked, &m);
+                    let click = ClickEvent {
+                        position: Some(p),
+                        button: b,
+                        modifiers: m,
+                        clicks,
+                    };
+                    handled = self.exec_handler(ElementEvent::Clicked, &click);
+                }
+            }
+            PrimitiveEvent::KeyInput(Some(vkc), _, s, m) => {
+                //enter and space press a focused button
+                if self.focus
+                    && self.enabled
+                    && s == properties::ButtonState::Pressed
+                    && (vkc == VirtualKeyCode::Return || vkc == VirtualKeyCode::Space)
+                {
+                    let click = ClickEvent {
+                        position: None,
+                        button: properties::Button::Left,
+                        modifiers: m,
+                        clicks: 1,
+                    };
+                    handled = self.exec_handler(ElementEvent::Clicked, &click);
+                }
+            }
+            PrimitiveEvent::SetFocus(f) => {
+                if self.focus != f && (self.enabled || !f) {
+                    self.focus = f;
+                    let focus = FocusEvent { old: !f, new: f };
+                    handled = self.exec_handler(ElementEvent::FocusChange, &focus);
                 }
             }
             PrimitiveEvent::HoverBegin(n_tags) => {
                 let matched = n_tags.iter().find(|x| x.0 == self.ext_id);
-                if matched.is_some() {
+                if matched.is_some() && !self.hovering {
                     self.hovering = true;
+                    let hover = HoverEvent { tags: n_tags };
+                    handled = self.exec_handler(ElementEvent::HoverBegin, &hover);
                 }
             }
             PrimitiveEvent::HoverEnd(o_tags) => {
                 let matched = o_tags.iter().find(|x| x.0 == self.ext_id);
-                if matched.is_some() {
+                if matched.is_some() && self.hovering {
                     self.hovering = false;
+                    let hover = HoverEvent { tags: o_tags };
+                    handled = self.exec_handler(ElementEvent::HoverEnd, &hover);
                 }
             }
             _ => (),
@@ -339,11 +383,18 @@ impl Element for Button {
     fn as_any_mut(&mut self) -> &mut Any {
         self
     }
+    fn is_focusable(&self) -> bool {
+        true
+    }
+    fn as_can_disable(&self) -> Option<&CanDisable> {
+        Some(self)
+    }
 }
 
 impl CanDisable for Button {
     fn set_enabled(&mut self, value: bool) {
         self.enabled = value;
+        Manager::tree_changed();
     }
 
     fn get_enabled(&self) -> bool {
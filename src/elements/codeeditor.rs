@@ -0,0 +1,574 @@
+use std::any::Any;
+use std::sync::Arc;
+
+use glutin::VirtualKeyCode;
+use webrender::api::*;
+
+use crate::elements::element::*;
+use crate::elements::textbox::{Decorations, Relayed, StyledRange, TextBox};
+use crate::gui::font;
+use crate::gui::properties;
+use crate::util::*;
+
+const GUTTER_PADDING: f32 = 8.0;
+const BRACKETS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
+//splits source text into coloured ranges
+pub trait Tokenizer: Send + Sync {
+    fn tokenize(&self, text: &[char]) -> Vec<StyledRange>;
+}
+
+fn monospace_family() -> &'static str {
+    if cfg!(target_os = "windows") {
+        "Consolas"
+    } else if cfg!(target_os = "macos") {
+        "Menlo"
+    } else {
+        "FreeMono"
+    }
+}
+
+fn line_start(text: &[char], index: usize) -> usize {
+    text[..index]
+        .iter()
+        .rposition(|c| *c == '\n')
+        .map_or(0, |i| i + 1)
+}
+
+fn line_end(text: &[char], index: usize) -> usize {
+    text[index..]
+        .iter()
+        .position(|c| *c == '\n')
+        .map_or(text.len(), |i| index + i)
+}
+
+//for every char, whether it is inside a double quoted string
+fn in_strings(text: &[char]) -> Vec<bool> {
+    let mut ret = Vec::with_capacity(text.len());
+    let mut inside = false;
+    let mut escaped = false;
+    for c in text.iter() {
+        if inside && !escaped && *c == '"' {
+            ret.push(true);
+            inside = false;
+            continue;
+        }
+        if !inside && *c == '"' {
+            inside = true;
+        }
+        ret.push(inside);
+        escaped = inside && !escaped && *c == '\\';
+    }
+    ret
+}
+
+//index of the bracket matching the one at index. Brackets in strings only
+//match brackets in strings, and those outside only those outside.
+fn match_bracket(text: &[char], index: usize) -> Option<usize> {
+    let c = text[index];
+    let strings = in_strings(text);
+    let quoted = strings[index];
+    for (open, close) in BRACKETS.iter() {
+        let forward = c == *open;
+        if !forward && c != *close {
+            continue;
+        }
+        let mut depth = 0;
+        let mut check = |i: usize| {
+            if strings[i] != quoted {
+                return false;
+            }
+            if text[i] == c {
+                depth += 1;
+            } else if text[i] == *open || text[i] == *close {
+                depth -= 1;
+            }
+            depth == 0
+        };
+        return if forward {
+            (index..text.len()).find(|i| check(*i))
+        } else {
+            (0..=index).rev().find(|i| check(*i))
+        };
+    }
+    None
+}
+
+//removes one level of indentation from the start of line
+fn strip_indent(line: &[char], tab_width: usize) -> &[char] {
+    if line.first() == Some(&'\t') {
+        return &line[1..];
+    }
+    let spaces = line.iter().take(tab_width).take_while(|c| **c == ' ').count();
+    &line[spaces..]
+}
+
+//handlers set on the editor get the CodeEditor, also for the events of the
+//TextBox, once the event that caused them was handled. Handlers set on
+//editor_mut() get the TextBox
+pub struct CodeEditor {
+    editor: TextBox,
+    handlers: EventHandlers,
+    tokenizer: Option<Box<Tokenizer>>,
+    styles: Vec<StyledRange>,
+    tokenized: Option<Vec<char>>, //text the styles were made for
+    tab_width: usize,
+    insert_spaces: bool,
+    line_numbers: bool,
+    shift: bool, //from the last key press, to tell tab from shift+tab
+}
+
+impl CodeEditor {
+    pub fn new(s: String) -> Self {
+        let mut editor = TextBox::new(s);
+        editor.set(properties::Property::Family(String::from(monospace_family())));
+        editor.set(properties::Property::Height(properties::Unit::Stretch(1.0)));
+        editor.set_relay(true);
+        CodeEditor {
+            editor,
+            handlers: EventHandlers::new(),
+            tokenizer: None,
+            styles: vec![],
+            tokenized: None,
+            tab_width: 4,
+            insert_spaces: true,
+            line_numbers: true,
+            shift: false,
+        }
+    }
+
+    pub fn set_value(&mut self, s: String) {
+        self.editor.set_value(s);
+    }
+
+    pub fn get_value(&self) -> String {
+        self.editor.get_value()
+    }
+
+    pub fn editor(&self) -> &TextBox {
+        &self.editor
+    }
+
+    pub fn editor_mut(&mut self) -> &mut TextBox {
+        &mut self.editor
+    }
+
+    pub fn set_tokenizer(&mut self, tokenizer: Option<Box<Tokenizer>>) {
+        self.tokenizer = tokenizer;
+        self.styles.clear();
+        self.tokenized = None;
+    }
+
+    pub fn set_tab_width(&mut self, tab_width: usize) {
+        self.tab_width = tab_width.max(1);
+    }
+
+    pub fn get_tab_width(&self) -> usize {
+        self.tab_width
+    }
+
+    //spaces by default
+    pub fn set_insert_spaces(&mut self, insert_spaces: bool) {
+        self.insert_spaces = insert_spaces;
+    }
+
+    pub fn set_line_numbers(&mut self, line_numbers: bool) {
+        self.line_numbers = line_numbers;
+    }
+
+    fn indent_unit(&self) -> String {
+        if self.insert_spaces {
+            " ".repeat(self.tab_width)
+        } else {
+            String::from("\t")
+        }
+    }
+
+    //the bracket at the cursor and its match, the one before the cursor goes first
+    fn find_bracket_pair(&self) -> Option<(usize, usize)> {
+        let text = self.editor.chars();
+        let cursor = self.editor.get_cursor_index();
+        [cursor.checked_sub(1), Some(cursor)]
+            .iter()
+            .filter_map(|i| *i)
+            .filter(|i| *i < text.len())
+            .filter_map(|i| match_bracket(text, i).map(|j| (i, j)))
+            .next()
+    }
+
+    //tab indents the selected lines, or inserts at the cursor when the
+    //selection is within a line. shift+tab takes one level off the lines.
+    fn indent(&mut self, dedent: bool) -> bool {
+        let text = self.editor.chars().to_vec();
+        let cursor = self.editor.get_cursor_index();
+        let selection = self.editor.get_selection();
+        let (start, end) = selection.unwrap_or((cursor, cursor));
+
+        if !dedent && !text[start..end].contains(&'\n') {
+            let unit = if self.insert_spaces {
+                let column = start - line_start(&text, start);
+                " ".repeat(self.tab_width - column % self.tab_width)
+            } else {
+                String::from("\t")
+            };
+            return self.editor.replace_range(start, end, &unit);
+        }
+
+        //a selection ending at the start of a line leaves that line alone
+        let first = line_start(&text, start);
+        let last = if end > start && text[end - 1] == '\n' {
+            end - 1
+        } else {
+            end
+        };
+        let last = line_end(&text, last);
+
+        let unit: Vec<char> = self.indent_unit().chars().collect();
+        let mut lines = vec![];
+        for line in text[first..last].split(|c| *c == '\n') {
+            let mut line = line.to_vec();
+            if dedent {
+                line = strip_indent(&line, self.tab_width).to_vec();
+            } else if !line.is_empty() {
+                line = unit.iter().chain(line.iter()).cloned().collect();
+            }
+            lines.push(line.iter().collect::<String>());
+        }
+        let replaced = lines.join("\n");
+        let len = replaced.chars().count();
+        if !self.editor.replace_range(first, last, &replaced) {
+            return false;
+        }
+        match selection {
+            Some(_) => self.editor.set_selection(first, first + len),
+            None => {
+                let removed = (last - first).saturating_sub(len);
+                self.editor.set_cursor_index(cursor.saturating_sub(removed).max(first));
+            }
+        }
+        true
+    }
+
+    //runs the handlers of the editor for the events its TextBox fired
+    fn relay(&mut self) -> bool {
+        let relayed: Vec<Relayed> = self.editor.take_relayed();
+        let mut handled = false;
+        for (e, d) in relayed.into_iter() {
+            handled = self.exec_handler(e, &*d) || handled;
+        }
+        handled
+    }
+
+    //a new line keeps the indentation of the current one, and goes a level
+    //deeper after an opening bracket
+    fn new_line(&mut self) -> bool {
+        let text = self.editor.chars();
+        let cursor = self.editor.get_cursor_index();
+        let (start, end) = self.editor.get_selection().unwrap_or((cursor, cursor));
+        let first = line_start(text, start);
+        let indent: String = text[first..start]
+            .iter()
+            .take_while(|c| **c == ' ' || **c == '\t')
+            .collect();
+        let mut insert = format!("\n{}", indent);
+        if start > first && BRACKETS.iter().any(|(open, _)| *open == text[start - 1]) {
+            insert.push_str(&self.indent_unit());
+        }
+        self.editor.replace_range(start, end, &insert)
+    }
+}
+
+impl Element for CodeEditor {
+    fn get_ext_id(&self) -> u64 {
+        self.editor.get_ext_id()
+    }
+
+    fn set(&mut self, prop: properties::Property) {
+        self.editor.set(prop);
+    }
+
+    fn get_properties(&self) -> properties::Properties {
+        self.editor.get_properties()
+    }
+
+    fn render(
+        &mut self,
+        api: &RenderApi,
+        builder: &mut DisplayListBuilder,
+        extent: properties::Extent,
+        font_store: &mut font::FontStore,
+        props: Option<Arc<properties::Properties>>,
+        gen: &mut properties::IdGenerator,
+    ) {
+        let p = self.editor.get_properties();
+        let size = p.get_size() as f32;
+        let family = p.get_family();
+        let focus = self.editor.has_focus();
+
+        if let Some(ref tokenizer) = self.tokenizer {
+            let text = self.editor.chars();
+            if self.tokenized.as_ref().map_or(true, |t| t.as_slice() != text) {
+                self.styles = tokenizer.tokenize(text);
+                self.tokenized = Some(text.to_vec());
+            }
+        }
+
+        let metrics = font_store.get_font_metrics(&family);
+        let baseline = match metrics {
+            Some(metrics) => {
+                let tmp = metrics.ascent - metrics.descent;
+                let tmp = size / tmp;
+                tmp * (metrics.ascent)
+            }
+            None => size,
+        };
+
+        //wide enough for the largest line number
+        let lines = self.editor.chars().iter().filter(|c| **c == '\n').count() + 1;
+        let gutter = if self.line_numbers {
+            let digits: Vec<char> = "0".repeat(lines.to_string().len().max(2)).chars().collect();
+            let mut paras = font_store.get_paragraphs(
+                &digits,
+                0.0,
+                0.0,
+                extent.w,
+                extent.h,
+                size,
+                baseline,
+                &family,
+                &properties::Align::Left,
+                &properties::WordBreak::Normal,
+                &[],
+                "",
+            );
+            paras.get_extent().w + GUTTER_PADDING * 2.0
+        } else {
+            0.0
+        };
+
+        let mut marks = vec![];
+        if focus {
+            if let Some((a, b)) = self.find_bracket_pair() {
+                let color = p.get_bracket_bg_color();
+                marks.push((a, color));
+                marks.push((b, color));
+            }
+        }
+        self.editor.set_decorations(Decorations {
+            styles: self.styles.clone(),
+            line_color: if focus {
+                Some(p.get_current_line_bg_color())
+            } else {
+                None
+            },
+            marks,
+            gutter,
+        });
+
+        self.editor.render(api, builder, extent, font_store, props, gen);
+
+        if !self.line_numbers {
+            return;
+        }
+
+        //the gutter is tagged as the editor, clicking it moves to the line
+        let bounds = self.editor.get_bounds();
+        let mut info = LayoutPrimitiveInfo::new((bounds.x, bounds.y).by(gutter, bounds.h));
+        info.tag = Some((self.editor.get_ext_id(), 0));
+        builder.push_rect(&info, p.get_gutter_bg_color());
+
+        let clip = builder.define_clip((bounds.x, bounds.y).by(gutter, bounds.h), vec![], None);
+        builder.push_clip_id(clip);
+
+        let (_, fi_key) = font_store.get_font_instance(&family, size as i32);
+        let color = p.get_gutter_color();
+        for (n, y) in self.editor.get_line_tops().iter().enumerate() {
+            if *y + size < bounds.y {
+                continue;
+            }
+            if *y > bounds.y + bounds.h {
+                break;
+            }
+            let number: Vec<char> = (n + 1).to_string().chars().collect();
+            let glyphs = font_store
+                .get_paragraphs(
+                    &number,
+                    bounds.x,
+                    *y,
+                    gutter - GUTTER_PADDING,
+                    size,
+                    size,
+                    baseline,
+                    &family,
+                    &properties::Align::Right,
+                    &properties::WordBreak::Normal,
+                    &[],
+                    "",
+                )
+                .glyphs();
+            let info = LayoutPrimitiveInfo::new((bounds.x, *y).by(gutter, size));
+            builder.push_text(&info, &glyphs, fi_key, color, Some(GlyphOptions::default()));
+        }
+
+        builder.pop_clip_id();
+    }
+
+    fn get_bounds(&self) -> properties::Extent {
+        self.editor.get_bounds()
+    }
+
+    fn on_primitive_event(&mut self, ext_ids: &[ItemTag], e: PrimitiveEvent) -> bool {
+        let editing =
+            self.editor.has_focus() && self.editor.get_editable() && self.editor.get_enabled();
+        if let PrimitiveEvent::KeyInput(_, _, _, ref m) = e {
+            self.shift = m.shift;
+        }
+        let handled = match e {
+            PrimitiveEvent::Char('\t') if editing => {
+                let dedent = self.shift;
+                self.indent(dedent);
+                true
+            }
+            PrimitiveEvent::Char(c) if editing && (c == '\r' || c == '\n') => {
+                self.new_line();
+                true
+            }
+            _ => self.editor.on_primitive_event(ext_ids, e),
+        };
+        let relayed = self.relay();
+        handled || relayed
+    }
+
+    fn set_handler(&mut self, e: ElementEvent, f: EventFn) {
+        self.handlers.insert(e, f);
+    }
+
+    fn exec_handler(&mut self, e: ElementEvent, d: &Any) -> bool {
+        let h = self.handlers.get_mut(&e).cloned();
+        if let Some(mut h) = h {
+            h.call(self, d)
+        } else {
+            false
+        }
+    }
+
+    fn as_any(&self) -> &Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut Any {
+        self
+    }
+    fn is_focusable(&self) -> bool {
+        true
+    }
+    fn wants_tab(&self) -> bool {
+        true
+    }
+    fn overrides_shortcut(&self, key: VirtualKeyCode, modifiers: &properties::Modifiers) -> bool {
+        key == VirtualKeyCode::Tab || self.editor.overrides_shortcut(key, modifiers)
+    }
+    fn as_can_disable(&self) -> Option<&CanDisable> {
+        Some(self)
+    }
+}
+
+impl CanDisable for CodeEditor {
+    fn set_enabled(&mut self, value: bool) {
+        self.editor.set_enabled(value);
+    }
+
+    fn get_enabled(&self) -> bool {
+        self.editor.get_enabled()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(s: &str) -> Vec<char> {
+        s.chars().collect()
+    }
+
+    fn find(s: &str, c: char, nth: usize) -> usize {
+        s.chars()
+            .enumerate()
+            .filter(|(_, x)| *x == c)
+            .nth(nth)
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn brackets_match_across_nesting() {
+        let s = "f(a[1], (b + c)) {}";
+        let text = chars(s);
+        assert_eq!(match_bracket(&text, find(s, '(', 0)), Some(find(s, ')', 1)));
+        assert_eq!(match_bracket(&text, find(s, ')', 1)), Some(find(s, '(', 0)));
+        assert_eq!(match_bracket(&text, find(s, '(', 1)), Some(find(s, ')', 0)));
+        assert_eq!(match_bracket(&text, find(s, '[', 0)), Some(find(s, ']', 0)));
+        assert_eq!(match_bracket(&text, find(s, '{', 0)), Some(find(s, '}', 0)));
+    }
+
+    #[test]
+    fn brackets_in_strings_are_skipped() {
+        let s = r#"f(")", "\")(", 1)"#;
+        let text = chars(s);
+        let last = text.len() - 1;
+        assert_eq!(match_bracket(&text, 1), Some(last));
+        assert_eq!(match_bracket(&text, last), Some(1));
+    }
+
+    #[test]
+    fn unbalanced_brackets_do_not_match() {
+        let text = chars("((a)");
+        assert_eq!(match_bracket(&text, 0), None);
+        assert_eq!(match_bracket(&text, 2), None);
+    }
+
+    #[test]
+    fn strip_indent_takes_one_level() {
+        assert_eq!(strip_indent(&chars("        x"), 4), &chars("    x")[..]);
+        assert_eq!(strip_indent(&chars("\t\tx"), 4), &chars("\tx")[..]);
+        assert_eq!(strip_indent(&chars("  x"), 4), &chars("x")[..]);
+        assert_eq!(strip_indent(&chars("x"), 4), &chars("x")[..]);
+    }
+
+    fn editor(value: &str, cursor: usize) -> CodeEditor {
+        let mut editor = CodeEditor::new(value.to_owned());
+        editor.editor_mut().set_cursor_index(cursor);
+        editor
+    }
+
+    #[test]
+    fn new_line_goes_deeper_after_opening_bracket() {
+        let mut ed = editor("    fn f() {", 12);
+        assert!(ed.new_line());
+        assert_eq!(ed.get_value(), "    fn f() {\n        ");
+        assert_eq!(ed.editor().get_cursor_index(), 21);
+    }
+
+    #[test]
+    fn new_line_keeps_indentation() {
+        let mut ed = editor("\tlet a = 1;", 11);
+        assert!(ed.new_line());
+        assert_eq!(ed.get_value(), "\tlet a = 1;\n\t");
+    }
+
+    #[test]
+    fn tab_indents_to_the_next_stop() {
+        let mut ed = editor("ab", 2);
+        assert!(ed.indent(false));
+        assert_eq!(ed.get_value(), "ab  ");
+    }
+
+    #[test]
+    fn tab_and_shift_tab_indent_selected_lines() {
+        let mut ed = editor("a\n  b\nc", 0);
+        ed.editor_mut().set_selection(0, 5);
+        assert!(ed.indent(false));
+        assert_eq!(ed.get_value(), "    a\n      b\nc");
+        assert!(ed.indent(true));
+        assert_eq!(ed.get_value(), "a\n  b\nc");
+    }
+}
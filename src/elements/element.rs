@@ -1,7 +1,9 @@
 use std::any::Any;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
 use std::mem;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
 use glutin;
@@ -23,6 +25,7 @@ pub enum PrimitiveEvent {
         properties::Button,
         properties::ButtonState,
         properties::Modifiers,
+        u32, //click count, 2 for a double click
     ),
     Char(char),
     KeyInput(
@@ -36,14 +39,44 @@ pub enum PrimitiveEvent {
     DPI(f64),
     HoverBegin(Vec<ItemTag>),
     HoverEnd(Vec<ItemTag>),
+    FileHovered(properties::Position, PathBuf),
+    FileDropped(properties::Position, PathBuf),
+    FileHoverCancelled,
+    Touch(
+        properties::Position,
+        properties::TouchPhase,
+        u64, //id of the touch point, the same from start to end
+    ),
+    Wheel(
+        properties::Position,
+        properties::ScrollDelta,
+        properties::Modifiers,
+    ),
 }
 
+//an untyped EventFn gets the payload of the marker in `on` as &Any, except Clicked
+//sends the Modifiers and FocusChange a bool
 #[derive(Debug, Clone, Eq)]
 pub enum ElementEvent {
     Clicked,
     FocusChange,
     HoverBegin,
     HoverEnd,
+    Changed,
+    Submit,
+    Capture,
+    Bubble,
+    DragStart,
+    DragEnter,
+    DragOver,
+    DragLeave,
+    Drop,
+    DragEnd,
+    Tap,
+    LongPress,
+    Pan,
+    Pinch,
+    Swipe,
 }
 
 impl Hash for ElementEvent {
@@ -66,7 +99,7 @@ impl PartialEq for ElementEvent {
 pub type EventClosure = FnMut(&mut Element, &Any) -> bool;
 
 #[derive(Clone)]
-pub struct EventFn(Arc<Mutex<EventClosure>>);
+pub struct EventFn(Arc<Mutex<EventClosure>>, bool); //true for handlers made with typed
 //it should be safe since Element will always be within a lock.
 //sending it as arc.mutex.element might end up in a deadlock
 unsafe impl Send for EventFn {}
@@ -75,11 +108,12 @@ unsafe impl Sync for EventFn {}
 use std::ops::DerefMut;
 impl EventFn {
     pub fn new(f: Arc<Mutex<EventClosure>>) -> EventFn {
-        EventFn(f)
+        EventFn(f, false)
     }
 
     pub fn call(&mut self, _e: &mut Element, _d: &Any) -> bool {
         //let h = ;//.unwrap()(_d)
+        let _d = if self.1 { _d } else { untyped_payload(_d) };
         if let Ok(mut f) = self.0.lock() {
             let x = f.deref_mut();
             x(_e, _d)
@@ -92,8 +126,302 @@ impl EventFn {
 //impl Copy for EventFn{}
 //impl Copy for FnMut<&mut Element, &Any> {}
 
+impl EventFn {
+    //wraps a handler for element type E and the payload of K, it returns false
+    //for other elements or data
+    pub fn typed<E, K, F>(f: F) -> EventFn
+    where
+        E: Element + 'static,
+        K: EventKind,
+        F: FnMut(&mut E, &K::Payload) -> bool + 'static,
+    {
+        let mut f = f;
+        let f = Arc::new(Mutex::new(move |e: &mut Element, d: &Any| {
+            match (e.as_any_mut().downcast_mut::<E>(), d.downcast_ref::<K::Payload>()) {
+                (Some(e), Some(d)) => f(e, d),
+                _ => false,
+            }
+        }));
+        EventFn(f, true)
+    }
+}
+
+//untyped handlers keep getting what Clicked and FocusChange always sent
+fn untyped_payload(d: &Any) -> &Any {
+    if let Some(click) = d.downcast_ref::<ClickEvent>() {
+        &click.modifiers
+    } else if let Some(focus) = d.downcast_ref::<FocusEvent>() {
+        &focus.new
+    } else {
+        d
+    }
+}
+
+//position is none for clicks from the keyboard
+#[derive(Debug, Clone)]
+pub struct ClickEvent {
+    pub position: Option<properties::Position>,
+    pub button: properties::Button,
+    pub modifiers: properties::Modifiers,
+    pub clicks: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct FocusEvent {
+    pub old: bool,
+    pub new: bool,
+}
+
+//the tags that started or stopped being under the cursor
+#[derive(Debug, Clone)]
+pub struct HoverEvent {
+    pub tags: Vec<ItemTag>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SubmitEvent {
+    pub value: String,
+}
+
+//a dragged payload of any type, files from the desktop are a Vec<PathBuf>
+#[derive(Clone)]
+pub struct DragData {
+    payload: Arc<Any + Send + Sync>,
+}
+
+impl DragData {
+    pub fn new<T: Any + Send + Sync>(payload: T) -> DragData {
+        DragData {
+            payload: Arc::new(payload),
+        }
+    }
+
+    pub fn get<T: Any>(&self) -> Option<&T> {
+        self.payload.downcast_ref::<T>()
+    }
+}
+
+//sent to the pressed element and its ancestors, the first to call start is the source
+pub struct DragStartEvent {
+    pub position: properties::Position,
+    data: RefCell<Option<DragData>>,
+}
+
+impl DragStartEvent {
+    pub fn new(position: properties::Position) -> DragStartEvent {
+        DragStartEvent {
+            position,
+            data: RefCell::new(None),
+        }
+    }
+
+    pub fn start(&self, data: DragData) {
+        *self.data.borrow_mut() = Some(data);
+    }
+
+    pub(crate) fn take_data(&self) -> Option<DragData> {
+        self.data.borrow_mut().take()
+    }
+}
+
+//for DragEnter, DragOver, DragLeave and Drop. DragOver handlers accept to allow a drop
+pub struct DragEvent {
+    pub position: properties::Position,
+    pub data: DragData,
+    accepted: Cell<bool>,
+}
+
+impl DragEvent {
+    pub fn new(position: properties::Position, data: DragData) -> DragEvent {
+        DragEvent {
+            position,
+            data,
+            accepted: Cell::new(false),
+        }
+    }
+
+    pub fn accept(&self) {
+        self.accepted.set(true);
+    }
+
+    pub fn is_accepted(&self) -> bool {
+        self.accepted.get()
+    }
+}
+
+pub struct DragEndEvent {
+    pub data: DragData,
+    pub dropped: bool,
+}
+
+//a tap nobody handles is a left click, a long press a right click
+#[derive(Debug, Clone)]
+pub struct TapEvent {
+    pub position: properties::Position,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GesturePhase {
+    Began,
+    Changed,
+    Ended,
+}
+
+//delta since the last pan, velocity in pixels per second. Unhandled pans scroll
+#[derive(Debug, Clone)]
+pub struct PanEvent {
+    pub position: properties::Position,
+    pub delta: (f32, f32),
+    pub velocity: (f32, f32),
+    pub phase: GesturePhase,
+}
+
+//scale is the distance of the fingers relative to the start
+#[derive(Debug, Clone)]
+pub struct PinchEvent {
+    pub center: properties::Position,
+    pub scale: f32,
+    pub phase: GesturePhase,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SwipeDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+//a pan that ended fast, velocity in pixels per second
+#[derive(Debug, Clone)]
+pub struct SwipeEvent {
+    pub position: properties::Position,
+    pub direction: SwipeDirection,
+    pub velocity: f32,
+}
+
+//ties an ElementEvent to the data its handlers get
+pub trait EventKind: 'static {
+    type Payload: 'static;
+    fn event() -> ElementEvent;
+}
+
+pub mod on {
+    use super::{
+        ClickEvent, DragEndEvent, DragEvent, DragStartEvent, ElementEvent, Event, EventKind,
+        FocusEvent, HoverEvent, PanEvent, PinchEvent, SubmitEvent, SwipeEvent, TapEvent,
+    };
+    use crate::elements::textbox::TextChange;
+
+    macro_rules! event_kind {
+        ($name:ident, $payload:ty) => {
+            pub struct $name;
+
+            impl EventKind for $name {
+                type Payload = $payload;
+                fn event() -> ElementEvent {
+                    ElementEvent::$name
+                }
+            }
+        };
+    }
+
+    event_kind!(Clicked, ClickEvent);
+    event_kind!(FocusChange, FocusEvent);
+    event_kind!(HoverBegin, HoverEvent);
+    event_kind!(HoverEnd, HoverEvent);
+    event_kind!(Changed, TextChange);
+    event_kind!(Submit, SubmitEvent);
+    event_kind!(Capture, Event);
+    event_kind!(Bubble, Event);
+    event_kind!(DragStart, DragStartEvent);
+    event_kind!(DragEnter, DragEvent);
+    event_kind!(DragOver, DragEvent);
+    event_kind!(DragLeave, DragEvent);
+    event_kind!(Drop, DragEvent);
+    event_kind!(DragEnd, DragEndEvent);
+    event_kind!(Tap, TapEvent);
+    event_kind!(LongPress, TapEvent);
+    event_kind!(Pan, PanEvent);
+    event_kind!(Pinch, PinchEvent);
+    event_kind!(Swipe, SwipeEvent);
+}
+
 pub type EventHandlers = HashMap<ElementEvent, EventFn>;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EventPhase {
+    Capture,
+    Target,
+    Bubble,
+}
+
+//a primitive event going from the root down to the target and back up
+#[derive(Debug)]
+pub struct Event {
+    primitive: PrimitiveEvent,
+    target: u64,
+    phase: Cell<EventPhase>,
+    propagation_stopped: Cell<bool>,
+    default_prevented: Cell<bool>,
+    handled: Cell<bool>,
+}
+
+impl Event {
+    pub fn new(primitive: PrimitiveEvent, target: u64) -> Event {
+        Event {
+            primitive,
+            target,
+            phase: Cell::new(EventPhase::Capture),
+            propagation_stopped: Cell::new(false),
+            default_prevented: Cell::new(false),
+            handled: Cell::new(false),
+        }
+    }
+
+    pub fn get_primitive(&self) -> &PrimitiveEvent {
+        &self.primitive
+    }
+
+    //ext id of the deepest element on the path
+    pub fn get_target(&self) -> u64 {
+        self.target
+    }
+
+    pub fn get_phase(&self) -> EventPhase {
+        self.phase.get()
+    }
+
+    pub(crate) fn set_phase(&self, phase: EventPhase) {
+        self.phase.set(phase);
+    }
+
+    pub fn stop_propagation(&self) {
+        self.propagation_stopped.set(true);
+    }
+
+    pub fn is_propagation_stopped(&self) -> bool {
+        self.propagation_stopped.get()
+    }
+
+    //elements do not act on the event, handlers still see it
+    pub fn prevent_default(&self) {
+        self.default_prevented.set(true);
+    }
+
+    pub fn is_default_prevented(&self) -> bool {
+        self.default_prevented.get()
+    }
+
+    pub fn is_handled(&self) -> bool {
+        self.handled.get()
+    }
+
+    pub(crate) fn set_handled(&self) {
+        self.handled.set(true);
+    }
+}
+
 pub trait Element: Send + Sync {
     fn get_ext_id(&self) -> u64;
     fn set(&mut self, prop: properties::Property);
@@ -119,6 +447,32 @@ pub trait Element: Send + Sync {
     }
     fn as_any(&self) -> &Any;
     fn as_any_mut(&mut self) -> &mut Any;
+    //children in tree order, containers list them so focus, capture, bubble,
+    //shortcuts and context menus reach the elements inside
+    fn get_children(&self) -> Vec<Arc<Mutex<Element>>> {
+        vec![]
+    }
+    fn is_focusable(&self) -> bool {
+        false
+    }
+    //tab goes to the element instead of moving focus
+    fn wants_tab(&self) -> bool {
+        false
+    }
+    fn as_can_disable(&self) -> Option<&CanDisable> {
+        None
+    }
+    //keeps a key bound as a window shortcut while focused
+    #[allow(unused)]
+    fn overrides_shortcut(&self, key: VirtualKeyCode, modifiers: &properties::Modifiers) -> bool {
+        false
+    }
+    fn on_capture(&mut self, e: &Event) {
+        self.exec_handler(ElementEvent::Capture, e);
+    }
+    fn on_bubble(&mut self, e: &Event) {
+        self.exec_handler(ElementEvent::Bubble, e);
+    }
     #[allow(unused)]
     fn on_event(
         &mut self,
@@ -143,8 +497,29 @@ pub trait HasChildren: Element {
     fn append(&mut self, e: Arc<Mutex<Element>>) -> Option<Arc<Mutex<Element>>> {
         None
     }
+    //before the child at i, or at the end
+    #[allow(unused)]
+    fn insert(&mut self, i: usize, e: Arc<Mutex<Element>>) {}
+    //returns where e was
+    #[allow(unused)]
+    fn remove(&mut self, e: &Arc<Mutex<Element>>) -> Option<usize> {
+        None
+    }
 }
 
+//e.g. button.on::<on::Clicked, _>(|button, click| ...)
+pub trait TypedHandlers: Element + Sized + 'static {
+    fn on<K, F>(&mut self, f: F)
+    where
+        K: EventKind,
+        F: FnMut(&mut Self, &K::Payload) -> bool + 'static,
+    {
+        self.set_handler(K::event(), EventFn::typed::<Self, K, F>(f));
+    }
+}
+
+impl<T: Element + 'static> TypedHandlers for T {}
+
 pub trait CanDisable: Element {
     fn set_enabled(&mut self, _: bool);
     fn get_enabled(&self) -> bool;
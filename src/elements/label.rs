@@ -0,0 +1,321 @@
+use std::any::Any;
+use std::sync::Arc;
+
+use glutin::VirtualKeyCode;
+use webrender::api::*;
+
+use crate::elements::element::*;
+use crate::gui::clipboard::{Clipboard, SystemClipboard};
+use crate::gui::font;
+use crate::gui::properties;
+use crate::gui::window::Manager;
+
+pub struct Label {
+    ext_id: u64,
+    value: Vec<char>,
+    props: properties::Properties,
+    bounds: properties::Extent,
+    event_handlers: EventHandlers,
+    selectable: bool,
+    focus: bool,
+    selecting: bool,
+    anchor: usize,
+    head: usize,
+    cache: font::Paragraphs,
+    clipboard: Box<Clipboard>,
+}
+
+impl Label {
+    pub fn new(s: String) -> Self {
+        let mut props = properties::Properties::new();
+        props.default();
+        props.set(properties::Property::Height(properties::Unit::Natural));
+        Label {
+            ext_id: 0,
+            value: s.chars().collect(),
+            props,
+            bounds: properties::Extent::new(),
+            event_handlers: EventHandlers::new(),
+            selectable: false,
+            focus: false,
+            selecting: false,
+            anchor: 0,
+            head: 0,
+            cache: font::Paragraphs::new(),
+            clipboard: Box::new(SystemClipboard),
+        }
+    }
+
+    pub fn set_value(&mut self, s: String) {
+        self.value = s.chars().collect();
+        self.anchor = 0;
+        self.head = 0;
+    }
+
+    pub fn get_value(&self) -> String {
+        self.value.clone().iter().collect()
+    }
+
+    pub fn set_selectable(&mut self, selectable: bool) {
+        self.selectable = selectable;
+        Manager::tree_changed();
+        if !selectable {
+            self.focus = false;
+            self.selecting = false;
+            self.anchor = 0;
+            self.head = 0;
+        }
+    }
+
+    pub fn get_selectable(&self) -> bool {
+        self.selectable
+    }
+
+    //logical start and end of the selection, None when nothing is selected
+    pub fn get_selection(&self) -> Option<(usize, usize)> {
+        if self.anchor == self.head {
+            None
+        } else if self.anchor < self.head {
+            Some((self.anchor, self.head))
+        } else {
+            Some((self.head, self.anchor))
+        }
+    }
+
+    pub fn set_selection(&mut self, start: usize, end: usize) {
+        let len = self.value.len();
+        self.anchor = start.min(len);
+        self.head = end.min(len);
+    }
+
+    pub fn get_selected_text(&self) -> String {
+        match self.get_selection() {
+            Some((start, end)) => self.value[start..end].iter().collect(),
+            None => String::new(),
+        }
+    }
+
+    //used for copying the selection
+    pub fn set_clipboard(&mut self, clipboard: Box<Clipboard>) {
+        self.clipboard = clipboard;
+    }
+
+    pub fn copy_selection(&mut self) {
+        if self.get_selection().is_some() {
+            let text = self.get_selected_text();
+            self.clipboard.set_contents(text);
+        }
+    }
+}
+
+impl Element for Label {
+    fn get_ext_id(&self) -> u64 {
+        self.ext_id
+    }
+
+    fn set(&mut self, prop: properties::Property) {
+        self.props.set(prop);
+    }
+
+    fn get_properties(&self) -> properties::Properties {
+        self.props.clone()
+    }
+
+    fn render(
+        &mut self,
+        _api: &RenderApi,
+        builder: &mut DisplayListBuilder,
+        extent: properties::Extent,
+        font_store: &mut font::FontStore,
+        _props: Option<Arc<properties::Properties>>,
+        gen: &mut properties::IdGenerator,
+    ) {
+        let _id = gen.get();
+        self.ext_id = _id;
+
+        let size = self.props.get_size() as f32;
+        let family = self.props.get_family();
+        let color = self.props.get_color();
+        let bgcolor = self.props.get_bg_color();
+        let selection_bgcolor = self.props.get_selection_bg_color();
+        let width = self.props.get_width();
+        let height = self.props.get_height();
+        let text_align = self.props.get_text_align();
+        let word_break = self.props.get_word_break();
+        let features = self.props.get_font_features();
+        let language = self.props.get_language();
+
+        let (_f_key, fi_key) = font_store.get_font_instance(&family, size as i32);
+
+        let metrics = font_store.get_font_metrics(&family);
+        let baseline = match metrics {
+            Some(metrics) => {
+                let tmp = metrics.ascent - metrics.descent;
+                let tmp = size / tmp;
+                tmp * (metrics.ascent)
+            }
+            None => size,
+        };
+
+        let mut paras = font_store.get_paragraphs(
+            &self.value,
+            extent.x,
+            extent.y,
+            extent.w,
+            extent.h,
+            size,
+            baseline,
+            &family,
+            &text_align,
+            &word_break,
+            &features,
+            &language,
+        );
+        let _bounds = paras.get_extent();
+        let glyphs = paras.glyphs();
+        self.cache = paras;
+
+        let calc_w = match width {
+            properties::Unit::Extent => extent.w,
+            properties::Unit::Pixel(px) => px,
+            properties::Unit::Stretch(s) => s * extent.w,
+            properties::Unit::Natural => _bounds.w,
+        };
+
+        let calc_h = match height {
+            properties::Unit::Extent => extent.h,
+            properties::Unit::Pixel(px) => px,
+            properties::Unit::Stretch(s) => s * extent.h,
+            properties::Unit::Natural => _bounds.h,
+        };
+
+        self.bounds = properties::Extent {
+            x: extent.x,
+            y: extent.y,
+            w: calc_w,
+            h: calc_h,
+            dpi: extent.dpi,
+        };
+
+        let mut info = LayoutPrimitiveInfo::new(LayoutRect::new(
+            LayoutPoint::new(extent.x, extent.y),
+            LayoutSize::new(self.bounds.w, self.bounds.h),
+        ));
+        info.tag = Some((_id, 0));
+        builder.push_rect(&info, bgcolor);
+
+        if let Some((start, end)) = self.get_selection() {
+            for r in self.cache.get_selection_extents(start, end) {
+                let info = LayoutPrimitiveInfo::new(LayoutRect::new(
+                    LayoutPoint::new(r.x, r.y),
+                    LayoutSize::new(r.w, r.h),
+                ));
+                builder.push_rect(&info, selection_bgcolor);
+            }
+        }
+
+        let info = LayoutPrimitiveInfo::new(LayoutRect::new(
+            LayoutPoint::new(extent.x, extent.y),
+            LayoutSize::new(self.bounds.w, self.bounds.h),
+        ));
+        builder.push_text(&info, &glyphs, fi_key, color, Some(GlyphOptions::default()));
+    }
+
+    fn get_bounds(&self) -> properties::Extent {
+        self.bounds.clone()
+    }
+
+    fn on_primitive_event(&mut self, ext_ids: &[ItemTag], e: PrimitiveEvent) -> bool {
+        let mut handled = false;
+        match e {
+            PrimitiveEvent::Button(p, b, s, m, clicks) => {
+                let on_self = !ext_ids.is_empty() && ext_ids[0].0 == self.ext_id;
+                if b == properties::Button::Left {
+                    if s == properties::ButtonState::Pressed {
+                        if on_self && self.selectable {
+                            let index = self.cache.get_caret_at_pos(&p);
+                            let (start, end) = match clicks {
+                                2 => font::word_at(&self.value, index),
+                                3 => font::line_at(&self.value, index),
+                                _ => (index, index),
+                            };
+                            self.anchor = start;
+                            self.head = end;
+                            self.selecting = clicks < 2;
+                            handled = true;
+                        }
+                    } else {
+                        self.selecting = false;
+                        if on_self {
+                            let click = ClickEvent {
+                                position: Some(p),
+                                button: b,
+                                modifiers: m,
+                                clicks,
+                            };
+                            handled = self.exec_handler(ElementEvent::Clicked, &click);
+                        }
+                    }
+                }
+            }
+            PrimitiveEvent::CursorMoved(p) => {
+                if self.selecting {
+                    self.head = self.cache.get_caret_at_pos(&p);
+                    handled = true;
+                }
+            }
+            PrimitiveEvent::Char(c) => {
+                //ctrl+c
+                if c == '\u{3}' && self.focus && self.selectable {
+                    self.copy_selection();
+                    handled = true;
+                }
+            }
+            PrimitiveEvent::SetFocus(f) => {
+                if self.selectable && self.focus != f {
+                    self.focus = f;
+                    if !f {
+                        self.selecting = false;
+                        self.head = self.anchor;
+                    }
+                    let focus = FocusEvent { old: !f, new: f };
+                    handled = self.exec_handler(ElementEvent::FocusChange, &focus);
+                }
+            }
+            _ => (),
+        }
+        handled
+    }
+
+    fn set_handler(&mut self, e: ElementEvent, f: EventFn) {
+        self.event_handlers.insert(e, f);
+    }
+
+    fn exec_handler(&mut self, e: ElementEvent, d: &Any) -> bool {
+        let h = self.event_handlers.get_mut(&e).cloned();
+        if let Some(mut h) = h {
+            h.call(self, d)
+        } else {
+            false
+        }
+    }
+
+    fn as_any(&self) -> &Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut Any {
+        self
+    }
+    fn is_focusable(&self) -> bool {
+        self.selectable
+    }
+    //ctrl+c copies the selection
+    fn overrides_shortcut(&self, key: VirtualKeyCode, modifiers: &properties::Modifiers) -> bool {
+        self.selectable
+            && key == VirtualKeyCode::C
+            && modifiers.ctrl
+            && !modifiers.shift
+            && !modifiers.alt
+            && !modifiers.logo
+    }
+}
@@ -0,0 +1,637 @@
+use std::any::Any;
+use std::sync::{Arc, Mutex};
+
+use glutin::VirtualKeyCode;
+use webrender::api::*;
+
+use crate::elements::element::*;
+use crate::gui::font;
+use crate::gui::properties;
+use crate::util::*;
+
+const PADDING: f32 = 4.0;
+const CHECK_WIDTH: f32 = 24.0; //column for the check marks
+const HINT_GAP: f32 = 32.0; //between the labels and the hints
+const SEPARATOR_HEIGHT: f32 = 9.0;
+const NO_WRAP_WIDTH: f32 = 1_000_000.0;
+
+//called with the checked state when an item is chosen
+pub type MenuAction = FnMut(bool) + Send;
+
+pub struct MenuItem {
+    label: String,
+    hint: Option<String>,
+    enabled: bool,
+    checkable: bool,
+    checked: bool,
+    submenu: Option<Menu>,
+    action: Option<Mutex<Box<MenuAction>>>, //the lock keeps the item Sync
+}
+
+impl MenuItem {
+    pub fn new(label: &str) -> MenuItem {
+        MenuItem {
+            label: label.to_owned(),
+            hint: None,
+            enabled: true,
+            checkable: false,
+            checked: false,
+            submenu: None,
+            action: None,
+        }
+    }
+
+    //the accelerator of the item, e.g. Shortcut::to_string()
+    pub fn with_hint(mut self, hint: &str) -> MenuItem {
+        self.hint = Some(hint.to_owned());
+        self
+    }
+
+    //the item toggles a check mark when chosen
+    pub fn with_check(mut self, checked: bool) -> MenuItem {
+        self.checkable = true;
+        self.checked = checked;
+        self
+    }
+
+    pub fn with_enabled(mut self, enabled: bool) -> MenuItem {
+        self.enabled = enabled;
+        self
+    }
+
+    pub fn with_submenu(mut self, menu: Menu) -> MenuItem {
+        self.submenu = Some(menu);
+        self
+    }
+
+    pub fn on_select<F: FnMut(bool) + Send + 'static>(mut self, f: F) -> MenuItem {
+        let action: Box<MenuAction> = Box::new(f);
+        self.action = Some(Mutex::new(action));
+        self
+    }
+
+    //items with a submenu show an arrow in place of the hint
+    fn get_hint(&self) -> Option<&str> {
+        if self.submenu.is_some() {
+            Some(">")
+        } else {
+            self.hint.as_ref().map(String::as_str)
+        }
+    }
+}
+
+enum Entry {
+    Item(MenuItem),
+    Separator,
+}
+
+//what a menu made of an event, submenus report back to their parent
+enum Outcome {
+    Ignored,
+    Handled,
+    Back, //left arrow, closes a submenu
+    Close,
+    Chosen,
+}
+
+//shown in an overlay above the window content, closes when an item is chosen,
+//on escape or on a click outside it
+pub struct Menu {
+    ext_id: u64,
+    entries: Vec<Entry>,
+    props: properties::Properties,
+    bounds: properties::Extent,
+    event_handlers: EventHandlers,
+    rows: Vec<properties::Extent>, //where each entry was drawn
+    position: properties::Position,
+    highlighted: Option<usize>,
+    open: Option<usize>, //entry whose submenu is showing
+    pressed: bool,
+    visible: bool,
+}
+
+impl Menu {
+    pub fn new() -> Self {
+        let mut props = properties::Properties::new();
+        props.default();
+        props
+            .set(properties::Property::Color(ColorF::new(0.1, 0.1, 0.1, 1.0)))
+            .set(properties::Property::BgColor(ColorF::new(0.97, 0.97, 0.97, 1.0)))
+            .set(properties::Property::HoverColor(ColorF::new(1.0, 1.0, 1.0, 1.0)))
+            .set(properties::Property::HoverBgColor(ColorF::new(0.2, 0.4, 0.8, 1.0)))
+            .set(properties::Property::DisabledColor(ColorF::new(0.6, 0.6, 0.6, 1.0)));
+        Menu {
+            ext_id: 0,
+            entries: vec![],
+            props,
+            bounds: properties::Extent::new(),
+            event_handlers: EventHandlers::new(),
+            rows: vec![],
+            position: properties::Position { x: 0.0, y: 0.0 },
+            highlighted: None,
+            open: None,
+            pressed: false,
+            visible: false,
+        }
+    }
+
+    pub fn add_item(&mut self, item: MenuItem) {
+        self.entries.push(Entry::Item(item));
+    }
+
+    pub fn add_separator(&mut self) {
+        self.entries.push(Entry::Separator);
+    }
+
+    //separators included
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn set_checked(&mut self, index: usize, checked: bool) {
+        if let Some(Entry::Item(item)) = self.entries.get_mut(index) {
+            item.checked = checked;
+        }
+    }
+
+    pub fn is_checked(&self, index: usize) -> bool {
+        match self.entries.get(index) {
+            Some(Entry::Item(item)) => item.checked,
+            _ => false,
+        }
+    }
+
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) {
+        if let Some(Entry::Item(item)) = self.entries.get_mut(index) {
+            item.enabled = enabled;
+        }
+    }
+
+    //moved in to fit the window, see Manager::show_menu
+    pub fn open_at(&mut self, p: properties::Position) {
+        self.close();
+        self.position = p;
+        self.visible = true;
+    }
+
+    pub fn close(&mut self) {
+        if let Some(sub) = self.get_submenu_mut() {
+            sub.close();
+        }
+        self.visible = false;
+        self.highlighted = None;
+        self.open = None;
+        self.pressed = false;
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.visible
+    }
+
+    //whether p is on the menu or one of its open submenus
+    pub fn contains(&self, p: &properties::Position) -> bool {
+        if !self.visible {
+            return false;
+        }
+        let b = &self.bounds;
+        let on_self = p.x >= b.x && p.x < b.x + b.w && p.y >= b.y && p.y < b.y + b.h;
+        on_self || self.get_submenu().map_or(false, |sub| sub.contains(p))
+    }
+
+    fn get_submenu(&self) -> Option<&Menu> {
+        match self.open.and_then(|i| self.entries.get(i)) {
+            Some(Entry::Item(item)) => item.submenu.as_ref(),
+            _ => None,
+        }
+    }
+
+    fn get_submenu_mut(&mut self) -> Option<&mut Menu> {
+        match self.open {
+            Some(i) => match self.entries.get_mut(i) {
+                Some(Entry::Item(item)) => item.submenu.as_mut(),
+                _ => None,
+            },
+            None => None,
+        }
+    }
+
+    fn is_enabled_item(&self, index: usize) -> bool {
+        match self.entries.get(index) {
+            Some(Entry::Item(item)) => item.enabled,
+            _ => false,
+        }
+    }
+
+    //the item drawn at p
+    fn row_at(&self, p: &properties::Position) -> Option<usize> {
+        self.rows
+            .iter()
+            .position(|r| p.x >= r.x && p.x < r.x + r.w && p.y >= r.y && p.y < r.y + r.h)
+            .filter(|i| self.is_enabled_item(*i))
+    }
+
+    //the next enabled item up or down from the highlighted one, wrapping around
+    fn step(&self, down: bool) -> Option<usize> {
+        let len = self.entries.len();
+        if len == 0 {
+            return None;
+        }
+        let mut i = match self.highlighted {
+            Some(i) => i,
+            None if down => len - 1,
+            None => 0,
+        };
+        for _ in 0..len {
+            i = if down { (i + 1) % len } else { (i + len - 1) % len };
+            if self.is_enabled_item(i) {
+                return Some(i);
+            }
+        }
+        None
+    }
+
+    //a highlighted item with a submenu shows it
+    fn highlight(&mut self, index: usize) {
+        if self.highlighted == Some(index) {
+            return;
+        }
+        if let Some(sub) = self.get_submenu_mut() {
+            sub.close();
+        }
+        self.highlighted = Some(index);
+        self.open = None;
+        let has_submenu = match self.entries.get(index) {
+            Some(Entry::Item(item)) => item.submenu.is_some(),
+            _ => false,
+        };
+        if has_submenu {
+            self.open = Some(index);
+            if let Some(sub) = self.get_submenu_mut() {
+                sub.visible = true;
+            }
+        }
+    }
+
+    //keyboard navigation moves into the submenu of the highlighted item
+    fn enter_submenu(&mut self) -> Outcome {
+        if let Some(i) = self.highlighted {
+            self.open = None;
+            self.highlighted = None;
+            self.highlight(i);
+            if let Some(sub) = self.get_submenu_mut() {
+                sub.highlighted = sub.step(true);
+            }
+        }
+        Outcome::Handled
+    }
+
+    fn activate(&mut self, index: usize) -> Outcome {
+        let has_submenu = match self.entries.get_mut(index) {
+            Some(Entry::Item(item)) if item.enabled => {
+                if item.submenu.is_none() {
+                    if item.checkable {
+                        item.checked = !item.checked;
+                    }
+                    let checked = item.checked;
+                    if let Some(ref action) = item.action {
+                        if let Ok(mut f) = action.lock() {
+                            (*f)(checked);
+                        }
+                    }
+                }
+                item.submenu.is_some()
+            }
+            _ => return Outcome::Handled,
+        };
+        if has_submenu {
+            self.highlighted = Some(index);
+            self.enter_submenu()
+        } else {
+            Outcome::Chosen
+        }
+    }
+
+    //a submenu closing with Escape or Left leaves this menu open
+    fn from_submenu(&mut self, outcome: Outcome) -> Outcome {
+        match outcome {
+            Outcome::Back | Outcome::Close => {
+                if let Some(sub) = self.get_submenu_mut() {
+                    sub.close();
+                }
+                self.open = None;
+                Outcome::Handled
+            }
+            outcome => outcome,
+        }
+    }
+
+    fn handle(&mut self, e: &PrimitiveEvent) -> Outcome {
+        match e {
+            PrimitiveEvent::CursorMoved(p) => {
+                let outcome = match self.get_submenu_mut() {
+                    Some(ref mut sub) if sub.contains(p) => Some(sub.handle(e)),
+                    _ => None,
+                };
+                if let Some(outcome) = outcome {
+                    return self.from_submenu(outcome);
+                }
+                match self.row_at(p) {
+                    Some(i) => {
+                        self.highlight(i);
+                        Outcome::Handled
+                    }
+                    None => Outcome::Ignored,
+                }
+            }
+            PrimitiveEvent::Button(p, _, s, _, _) => {
+                let outcome = match self.get_submenu_mut() {
+                    Some(ref mut sub) if sub.contains(p) => Some(sub.handle(e)),
+                    _ => None,
+                };
+                if let Some(outcome) = outcome {
+                    return self.from_submenu(outcome);
+                }
+                //a release only chooses after a press on the menu, so the
+                //release of the click that opened it does not
+                match s {
+                    properties::ButtonState::Pressed => self.pressed = true,
+                    properties::ButtonState::Released => {
+                        if self.pressed {
+                            self.pressed = false;
+                            if let Some(i) = self.row_at(p) {
+                                return self.activate(i);
+                            }
+                        }
+                    }
+                }
+                Outcome::Handled
+            }
+            PrimitiveEvent::KeyInput(Some(vkc), _, properties::ButtonState::Pressed, _) => {
+                let outcome = match self.get_submenu_mut() {
+                    Some(ref mut sub) if sub.highlighted.is_some() => Some(sub.handle(e)),
+                    _ => None,
+                };
+                if let Some(outcome) = outcome {
+                    return self.from_submenu(outcome);
+                }
+                match vkc {
+                    VirtualKeyCode::Up | VirtualKeyCode::Down => {
+                        if let Some(i) = self.step(*vkc == VirtualKeyCode::Down) {
+                            self.highlight(i);
+                        }
+                        Outcome::Handled
+                    }
+                    VirtualKeyCode::Right => self.enter_submenu(),
+                    VirtualKeyCode::Left => Outcome::Back,
+                    VirtualKeyCode::Escape => Outcome::Close,
+                    VirtualKeyCode::Return | VirtualKeyCode::Space => match self.highlighted {
+                        Some(i) => self.activate(i),
+                        None => Outcome::Handled,
+                    },
+                    _ => Outcome::Handled,
+                }
+            }
+            _ => Outcome::Ignored,
+        }
+    }
+
+    fn layout_text(
+        &self,
+        text: &str,
+        x: f32,
+        y: f32,
+        baseline: f32,
+        font_store: &mut font::FontStore,
+    ) -> font::Paragraphs {
+        let size = self.props.get_size() as f32;
+        let chars: Vec<char> = text.chars().collect();
+        font_store.get_paragraphs(
+            &chars,
+            x,
+            y,
+            NO_WRAP_WIDTH,
+            size * 1.5,
+            size,
+            baseline,
+            &self.props.get_family(),
+            &properties::Align::Left,
+            &properties::WordBreak::Normal,
+            &self.props.get_font_features(),
+            &self.props.get_language(),
+        )
+    }
+}
+
+impl Default for Menu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Element for Menu {
+    fn get_ext_id(&self) -> u64 {
+        self.ext_id
+    }
+
+    fn set(&mut self, prop: properties::Property) {
+        self.props.set(prop);
+    }
+
+    fn get_properties(&self) -> properties::Properties {
+        self.props.clone()
+    }
+
+    //the extent is the area the menu has to fit in, it draws itself at the
+    //position it was opened at
+    fn render(
+        &mut self,
+        api: &RenderApi,
+        builder: &mut DisplayListBuilder,
+        extent: properties::Extent,
+        font_store: &mut font::FontStore,
+        _props: Option<Arc<properties::Properties>>,
+        gen: &mut properties::IdGenerator,
+    ) {
+        let _id = gen.get();
+        self.ext_id = _id;
+        if !self.visible {
+            self.bounds = properties::Extent::new();
+            self.rows.clear();
+            return;
+        }
+
+        let size = self.props.get_size() as f32;
+        let family = self.props.get_family();
+        let color = self.props.get_color();
+        let bgcolor = self.props.get_bg_color();
+        let hover_color = self.props.get_hover_color();
+        let hover_bgcolor = self.props.get_hover_bg_color();
+        let disabled_color = self.props.get_disabled_color();
+
+        let (_f_key, fi_key) = font_store.get_font_instance(&family, size as i32);
+        let baseline = match font_store.get_font_metrics(&family) {
+            Some(metrics) => size / (metrics.ascent - metrics.descent) * metrics.ascent,
+            None => size,
+        };
+        let row_h = size * 1.5;
+
+        //measure the labels and the hints, submenus get an arrow as hint
+        let mut label_w: f32 = 0.0;
+        let mut hint_w: f32 = 0.0;
+        for entry in self.entries.iter() {
+            if let Entry::Item(item) = entry {
+                let mut paras = self.layout_text(&item.label, 0.0, 0.0, baseline, font_store);
+                label_w = label_w.max(paras.get_extent().w);
+                if let Some(hint) = item.get_hint() {
+                    let mut paras = self.layout_text(hint, 0.0, 0.0, baseline, font_store);
+                    hint_w = hint_w.max(paras.get_extent().w);
+                }
+            }
+        }
+        let w = CHECK_WIDTH + label_w + if hint_w > 0.0 { HINT_GAP + hint_w } else { 0.0 } + PADDING * 3.0;
+        let h = self
+            .entries
+            .iter()
+            .map(|e| match e {
+                Entry::Item(_) => row_h,
+                Entry::Separator => SEPARATOR_HEIGHT,
+            })
+            .sum::<f32>()
+            + PADDING * 2.0;
+
+        //keep the menu inside the area
+        let x = self.position.x.min(extent.x + extent.w - w).max(extent.x);
+        let y = self.position.y.min(extent.y + extent.h - h).max(extent.y);
+        self.bounds = properties::Extent {
+            x,
+            y,
+            w,
+            h,
+            dpi: extent.dpi,
+        };
+
+        let mut info = LayoutPrimitiveInfo::new((x - 1.0, y - 1.0).by(w + 2.0, h + 2.0));
+        info.tag = Some((_id, 0));
+        builder.push_rect(&info, disabled_color);
+        builder.push_rect(&LayoutPrimitiveInfo::new((x, y).by(w, h)), bgcolor);
+
+        self.rows.clear();
+        let mut top = y + PADDING;
+        for (i, entry) in self.entries.iter().enumerate() {
+            match entry {
+                Entry::Separator => {
+                    let row = properties::Extent {
+                        x,
+                        y: top,
+                        w,
+                        h: SEPARATOR_HEIGHT,
+                        dpi: extent.dpi,
+                    };
+                    builder.push_rect(
+                        &LayoutPrimitiveInfo::new(
+                            (x + PADDING, top + SEPARATOR_HEIGHT / 2.0).by(w - PADDING * 2.0, 1.0),
+                        ),
+                        disabled_color,
+                    );
+                    top += SEPARATOR_HEIGHT;
+                    self.rows.push(row);
+                }
+                Entry::Item(item) => {
+                    let row = properties::Extent {
+                        x,
+                        y: top,
+                        w,
+                        h: row_h,
+                        dpi: extent.dpi,
+                    };
+                    let text_color = if !item.enabled {
+                        disabled_color
+                    } else if self.highlighted == Some(i) {
+                        builder.push_rect(&LayoutPrimitiveInfo::new((x, top).by(w, row_h)), hover_bgcolor);
+                        hover_color
+                    } else {
+                        color
+                    };
+
+                    if item.checked {
+                        let mark = size * 0.5;
+                        builder.push_rect(
+                            &LayoutPrimitiveInfo::new(
+                                (x + (CHECK_WIDTH - mark) / 2.0 + PADDING, top + (row_h - mark) / 2.0)
+                                    .by(mark, mark),
+                            ),
+                            text_color,
+                        );
+                    }
+
+                    let text_y = top + (row_h - size) / 2.0;
+                    let paras = self.layout_text(&item.label, x + PADDING + CHECK_WIDTH, text_y, baseline, font_store);
+                    let info = LayoutPrimitiveInfo::new((x, top).by(w, row_h));
+                    builder.push_text(&info, &paras.glyphs(), fi_key, text_color, Some(GlyphOptions::default()));
+
+                    if let Some(hint) = item.get_hint() {
+                        let hint_x = x + w - PADDING * 2.0 - hint_w;
+                        let paras = self.layout_text(hint, hint_x, text_y, baseline, font_store);
+                        builder.push_text(&info, &paras.glyphs(), fi_key, text_color, Some(GlyphOptions::default()));
+                    }
+
+                    top += row_h;
+                    self.rows.push(row);
+                }
+            }
+        }
+
+        //the open submenu goes next to its item, or over the menu when
+        //there is no room on the right
+        let open_row = self.open.and_then(|i| self.rows.get(i).cloned());
+        if let (Some(row), Some(sub)) = (open_row, self.get_submenu_mut()) {
+            sub.position = properties::Position {
+                x: row.x + row.w,
+                y: row.y - PADDING,
+            };
+            sub.render(api, builder, extent, font_store, None, gen);
+        }
+    }
+
+    fn get_bounds(&self) -> properties::Extent {
+        self.bounds.clone()
+    }
+
+    fn on_primitive_event(&mut self, _ext_ids: &[ItemTag], e: PrimitiveEvent) -> bool {
+        if !self.visible {
+            return false;
+        }
+        match self.handle(&e) {
+            Outcome::Chosen | Outcome::Close => {
+                self.close();
+                true
+            }
+            Outcome::Handled | Outcome::Back => true,
+            Outcome::Ignored => false,
+        }
+    }
+
+    fn set_handler(&mut self, e: ElementEvent, f: EventFn) {
+        self.event_handlers.insert(e, f);
+    }
+
+    fn exec_handler(&mut self, e: ElementEvent, d: &Any) -> bool {
+        let h = self.event_handlers.get_mut(&e).cloned();
+        if let Some(mut h) = h {
+            h.call(self, d)
+        } else {
+            false
+        }
+    }
+
+    fn as_any(&self) -> &Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut Any {
+        self
+    }
+}
@@ -1,15 +1,21 @@
 mod button;
+mod codeeditor;
 mod element;
 mod hbox;
 mod image;
+mod label;
+mod menu;
 mod scrollbox;
 mod textbox;
 mod vbox;
 
 pub use self::button::Button;
+pub use self::codeeditor::{CodeEditor, Tokenizer};
 pub use self::element::*;
 pub use self::hbox::HBox;
 pub use self::image::*;
+pub use self::label::Label;
+pub use self::menu::{Menu, MenuAction, MenuItem};
 pub use self::scrollbox::ScrollBox;
-pub use self::textbox::TextBox;
+pub use self::textbox::{CharFilter, StyledRange, TextBox, TextChange, Validation, Validator};
 pub use self::vbox::VBox;
@@ -7,6 +7,7 @@ use webrender::api::*;
 use crate::elements::element::*;
 use crate::gui::font;
 use crate::gui::properties;
+use crate::gui::window::Manager;
 use crate::util::*;
 
 pub struct ScrollBox {
@@ -158,6 +159,16 @@ impl Element for ScrollBox {
                         _child_elm.on_primitive_event(&[], PrimitiveEvent::SetFocus(false));
                     }
                 }
+                //the window dispatches these along the event path. They are
+                //only passed on when nothing was targeted, or for the tags of
+                //children the window could not find (no get_children)
+                (PrimitiveEvent::Button(..), _)
+                | (PrimitiveEvent::Char(_), _)
+                | (PrimitiveEvent::KeyInput(..), _)
+                | (PrimitiveEvent::CursorMoved(_), _)
+                | (PrimitiveEvent::Wheel(..), _)
+                | (PrimitiveEvent::Touch(..), _)
+                    if ext_ids.len() == 1 => {}
                 (PrimitiveEvent::Char(_c), Ok(ref mut _child_elm)) => {
                     handled = _child_elm.on_primitive_event(&[], e.clone());
                 }
@@ -186,8 +197,14 @@ impl Element for ScrollBox {
         // if none of the children handled the event
         // see if you can handle it here
         if !handled {
-            if let PrimitiveEvent::Button(_p, _b, _s, m) = e {
-                handled = self.exec_handler(ElementEvent::Clicked, &m);
+            if let PrimitiveEvent::Button(p, b, _s, m, clicks) = e {
+                let click = ClickEvent {
+                    position: Some(p),
+                    button: b,
+                    modifiers: m,
+                    clicks,
+                };
+                handled = self.exec_handler(ElementEvent::Clicked, &click);
             }
         }
         handled
@@ -212,6 +229,9 @@ impl Element for ScrollBox {
     fn as_any_mut(&mut self) -> &mut Any {
         self
     }
+    fn get_children(&self) -> Vec<Arc<Mutex<Element>>> {
+        self.child.iter().cloned().collect()
+    }
 }
 
 impl Default for ScrollBox {
@@ -230,6 +250,7 @@ impl HasChildren for ScrollBox {
     fn append(&mut self, e: Arc<Mutex<Element>>) -> Option<Arc<Mutex<Element>>> {
         let mut ret = Some(e);
         mem::swap(&mut self.child, &mut ret);
+        Manager::tree_changed();
         ret
     }
 }
@@ -1,13 +1,199 @@
 use std::any::Any;
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use clipboard::{ClipboardContext, ClipboardProvider};
 use glutin::VirtualKeyCode;
 use webrender::api::*;
 
 use crate::elements::element::*;
+use crate::gui::clipboard::{Clipboard, SystemClipboard};
 use crate::gui::font;
 use crate::gui::properties;
+use crate::gui::window::Manager;
+use crate::util::*;
+
+//edits of the same kind following each other are undone together
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EditKind {
+    Insert,
+    Delete,
+    Other,
+}
+
+//value, cursor and selection before an edit
+#[derive(Debug, Clone)]
+struct EditState {
+    value: Vec<char>,
+    cursor: usize,
+    anchor: usize,
+}
+
+const UNDO_LIMIT: usize = 100;
+//a pause in typing this long ends the undo step
+const UNDO_BURST_GAP: Duration = Duration::from_secs(1);
+const SCROLLBAR_WIDTH: f32 = 8.0;
+const SCROLLBAR_TAG: u16 = 1;
+//wide enough that a single line never wraps
+const NO_WRAP_WIDTH: f32 = 1_000_000.0;
+
+//what a validator makes of the value an edit would produce
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Validation {
+    Valid,
+    //the edit is kept but the box is drawn with the error colors
+    Invalid,
+    //the edit is thrown away
+    Reject,
+}
+
+//start..old_end of the old value was replaced by start..new_end, in chars
+#[derive(Debug, Clone)]
+pub struct TextChange {
+    pub old_value: String,
+    pub new_value: String,
+    pub start: usize,
+    pub old_end: usize,
+    pub new_end: usize,
+}
+
+impl TextChange {
+    fn new(old: &[char], new: &[char], start: usize, old_end: usize, new_end: usize) -> Self {
+        TextChange {
+            old_value: old.iter().collect(),
+            new_value: new.iter().collect(),
+            start,
+            old_end,
+            new_end,
+        }
+    }
+
+    //for changes whose range is not known, like undo, the common prefix and
+    //suffix of the two values
+    fn diff(old: &[char], new: &[char]) -> Self {
+        let start = old.iter().zip(new.iter()).take_while(|(a, b)| a == b).count();
+        TextChange::diff_from(old, new, start)
+    }
+
+    //the change is known to start at start, e.g. a masked edit
+    fn diff_from(old: &[char], new: &[char], start: usize) -> Self {
+        let max_suffix = old.len().min(new.len()).saturating_sub(start);
+        let suffix = old
+            .iter()
+            .rev()
+            .zip(new.iter().rev())
+            .take(max_suffix)
+            .take_while(|(a, b)| a == b)
+            .count();
+        TextChange::new(old, new, start, old.len() - suffix, new.len() - suffix)
+    }
+}
+
+//a colour for the chars start..end
+#[derive(Debug, Clone, PartialEq)]
+pub struct StyledRange {
+    pub start: usize,
+    pub end: usize,
+    pub color: ColorF,
+}
+
+//extra drawing for elements built on top of TextBox, like CodeEditor
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Decorations {
+    pub styles: Vec<StyledRange>,
+    pub line_color: Option<ColorF>, //background of the line with the cursor
+    pub marks: Vec<(usize, ColorF)>, //chars with a background, e.g. matched brackets
+    pub gutter: f32,                //space kept free left of the text
+}
+
+//an event a TextBox fired and its data, kept for the element it is part of
+pub(crate) type Relayed = (ElementEvent, Box<Any + Send + Sync>);
+
+pub type CharFilter = Fn(char) -> bool + Send + Sync;
+pub type Validator = Fn(&str) -> Validation + Send + Sync;
+
+//what a mask character accepts, None for literals
+fn mask_slot(m: char) -> Option<fn(char) -> bool> {
+    let accepts: fn(char) -> bool = match m {
+        '9' => char::is_numeric,
+        'a' => char::is_alphabetic,
+        '*' => char::is_alphanumeric,
+        _ => return None,
+    };
+    Some(accepts)
+}
+
+//replaces start..end of a masked value with text. Typed chars go into the
+//slots of the mask, a char equal to the literal at its position is taken as
+//that literal. Returns the new value and where the cursor ends up, None if a
+//char does not fit.
+fn apply_mask(
+    mask: &[char],
+    value: &[char],
+    start: usize,
+    end: usize,
+    text: &[char],
+) -> Option<(Vec<char>, usize)> {
+    let is_literal = |i: usize| mask_slot(mask[i]).is_none();
+    let slots: Vec<usize> = (0..mask.len()).filter(|i| !is_literal(*i)).collect();
+    let mut raw: Vec<char> = slots.iter().filter_map(|s| value.get(*s).cloned()).collect();
+    //slots before a position of the value
+    let raw_at = |index: usize| slots.iter().take_while(|s| **s < index).count().min(raw.len());
+    let (raw_start, raw_end) = (raw_at(start), raw_at(end));
+
+    let mut typed = vec![];
+    let mut pos = start;
+    for c in text {
+        if pos < mask.len() && is_literal(pos) && mask[pos] == *c {
+            pos += 1;
+            continue;
+        }
+        while pos < mask.len() && is_literal(pos) {
+            pos += 1;
+        }
+        match mask.get(pos).and_then(|m| mask_slot(*m)) {
+            Some(accepts) if accepts(*c) => {
+                typed.push(*c);
+                pos += 1;
+            }
+            //separators typed out of place are dropped
+            _ if (0..mask.len()).any(|i| is_literal(i) && mask[i] == *c) => (),
+            _ => return None,
+        }
+    }
+
+    raw.splice(raw_start..raw_end, typed);
+    if raw.len() > slots.len() {
+        return None;
+    }
+    let mut out = vec![];
+    for (c, slot) in raw.iter().zip(slots.iter()) {
+        if !mask_slot(mask[*slot]).map_or(false, |accepts| accepts(*c)) {
+            return None;
+        }
+        let filled = out.len();
+        out.extend_from_slice(&mask[filled..*slot]);
+        out.push(*c);
+    }
+
+    //literals typed at the end are kept
+    if pos > out.len() {
+        let filled = out.len();
+        out.extend_from_slice(&mask[filled..pos]);
+    }
+    Some((out, pos.min(out.len())))
+}
+
+//the slot the caret moves back to when deleting before index, skipping literals
+fn slot_before(mask: &[char], index: usize) -> Option<usize> {
+    (0..index.min(mask.len())).rev().find(|i| mask_slot(mask[*i]).is_some())
+}
+
+//the first slot at or after index
+fn slot_after(mask: &[char], index: usize) -> Option<usize> {
+    (index..mask.len()).find(|i| mask_slot(mask[*i]).is_some())
+}
 
 pub struct TextBox {
     ext_id: u64,
@@ -21,19 +207,43 @@ pub struct TextBox {
     editable: bool,
     enabled: bool,
     singleline: bool,
-    cursor: Option<(font::Char, properties::Position)>,
-    cursor_index: usize,
-    cursor_after: bool,
+    cursor: usize, //logical caret index, 0 to value.len()
+    anchor: usize, //other end of the selection, same as cursor when none
+    column: Option<f32>, //x the cursor keeps to when moving up and down
+    selecting: bool,
     hovering: bool,
     is_password: bool,
     cache: font::Paragraphs,
+    clipboard: Box<Clipboard>,
+    undo_stack: Vec<EditState>,
+    redo_stack: Vec<EditState>,
+    last_edit: Option<(EditKind, Instant)>, //kind and time of the burst being typed
+    max_length: Option<usize>,
+    filter: Option<Arc<CharFilter>>,
+    mask: Vec<char>,
+    validator: Option<Arc<Validator>>,
+    invalid: bool,
+    content: properties::Extent, //size of the laid out text
+    view_w: f32,                 //width of the text area, less the scrollbar
+    scroll_x: f32,
+    scroll_y: f32,
+    follow_caret: bool,
+    thumb_drag: Option<f32>, //where the scrollbar thumb was grabbed
+    decorations: Decorations,
+    caret_blink: Option<Duration>,
+    caret_on: Arc<AtomicBool>, //flipped by the blink timer
+    blink_timer: Option<u64>,
+    accepts_tab: bool,
+    relay: Option<Vec<Relayed>>,
 }
 
 impl TextBox {
     pub fn new(s: String) -> Self {
         let mut props = properties::Properties::new();
         props.default();
-        props.set(properties::Property::Height(properties::Unit::Natural));
+        props
+            .set(properties::Property::Height(properties::Unit::Natural))
+            .set(properties::Property::Cursor(properties::Cursor::Text));
         TextBox {
             ext_id: 0,
             value: s.chars().collect(),
@@ -52,18 +262,44 @@ impl TextBox {
             editable: true,
             enabled: true,
             singleline: false,
-            cursor: None,
-            cursor_index: 0,
-            cursor_after: false,
+            cursor: 0,
+            anchor: 0,
+            column: None,
+            selecting: false,
             hovering: false,
             is_password: false,
             cache: font::Paragraphs::new(),
+            clipboard: Box::new(SystemClipboard),
+            undo_stack: vec![],
+            redo_stack: vec![],
+            last_edit: None,
+            max_length: None,
+            filter: None,
+            mask: vec![],
+            validator: None,
+            invalid: false,
+            content: properties::Extent::new(),
+            view_w: 0.0,
+            scroll_x: 0.0,
+            scroll_y: 0.0,
+            follow_caret: false,
+            thumb_drag: None,
+            decorations: Decorations::default(),
+            caret_blink: Some(Duration::from_millis(530)),
+            caret_on: Arc::new(AtomicBool::new(true)),
+            blink_timer: None,
+            accepts_tab: false,
+            relay: None,
         }
     }
 
     pub fn set_value(&mut self, s: String) {
         self.value = s.chars().collect();
+        self.cursor = self.cursor.min(self.value.len());
+        self.anchor = self.cursor;
         self.drawn = 0;
+        self.clear_history();
+        self.validate();
     }
 
     pub fn append_value(&mut self, s: &str) {
@@ -81,6 +317,7 @@ impl TextBox {
         self.drawn = 0;
         if !editable {
             self.focus = false;
+            self.stop_blink();
         }
     }
 
@@ -96,20 +333,537 @@ impl TextBox {
         self.singleline = singleline;
     }
 
-    pub fn get_cursor_index(&self) -> (usize,bool) {
-        match self.cursor {
-            None => (0,false),
-            Some((ref ch, ref p)) => {
-                let mut ind = ch.get_index();
-                let pos = ch.get_position();
-                let adv = ch.get_metric().advance;
-                let mut after = false;
-                if p.x > pos.x + (adv.x/2.) {
-                    //ind += 1;
-                    after = true;
+    //by default tab moves the focus on
+    pub fn set_accepts_tab(&mut self, accepts_tab: bool) {
+        self.accepts_tab = accepts_tab;
+    }
+
+    pub fn get_accepts_tab(&self) -> bool {
+        self.accepts_tab
+    }
+
+    //none for no limit
+    pub fn set_max_length(&mut self, max_length: Option<usize>) {
+        self.max_length = max_length;
+    }
+
+    pub fn get_max_length(&self) -> Option<usize> {
+        self.max_length
+    }
+
+    //only chars the filter allows can be typed or pasted
+    pub fn set_filter(&mut self, filter: Option<Arc<CharFilter>>) {
+        self.filter = filter;
+    }
+
+    //e.g. (999) 999-9999, 9 takes a digit, a a letter and * either, an empty
+    //mask turns masking off
+    pub fn set_mask(&mut self, mask: String) {
+        self.mask = mask.chars().collect();
+    }
+
+    pub fn get_mask(&self) -> String {
+        self.mask.iter().collect()
+    }
+
+    //called with the value every edit would produce, it can reject the edit
+    pub fn set_validator(&mut self, validator: Option<Arc<Validator>>) {
+        self.validator = validator;
+        self.validate();
+    }
+
+    pub fn is_valid(&self) -> bool {
+        !self.invalid
+    }
+
+    //a rejection counts as invalid
+    pub fn validate(&mut self) -> bool {
+        self.invalid = match self.validator {
+            Some(ref v) => v(&self.get_value()) != Validation::Valid,
+            None => false,
+        };
+        !self.invalid
+    }
+
+    pub fn get_scroll(&self) -> (f32, f32) {
+        (self.scroll_x, self.scroll_y)
+    }
+
+    //the offsets are clamped to the content when rendered
+    pub fn set_scroll(&mut self, x: f32, y: f32) {
+        self.scroll_x = x;
+        self.scroll_y = y;
+        self.follow_caret = false;
+    }
+
+    pub fn scroll_by(&mut self, dx: f32, dy: f32) {
+        let (x, y) = (self.scroll_x + dx, self.scroll_y + dy);
+        let (max_x, max_y) = self.get_max_scroll();
+        self.set_scroll(x.min(max_x).max(0.0), y.min(max_y).max(0.0));
+    }
+
+    fn get_max_scroll(&self) -> (f32, f32) {
+        (
+            (self.content.w - self.view_w).max(0.0),
+            (self.content.h - self.bounds.h).max(0.0),
+        )
+    }
+
+    //the scrollbar thumb, sized by how much of the text is in view
+    fn get_thumb(&self) -> properties::Extent {
+        let h = self.bounds.h;
+        let (_, max_y) = self.get_max_scroll();
+        let thumb_h = if self.content.h > 0.0 {
+            (h * h / self.content.h).max(SCROLLBAR_WIDTH * 2.0).min(h)
+        } else {
+            h
+        };
+        let y = if max_y > 0.0 {
+            self.bounds.y + (h - thumb_h) * self.scroll_y / max_y
+        } else {
+            self.bounds.y
+        };
+        properties::Extent {
+            x: self.bounds.x + self.decorations.gutter + self.view_w,
+            y,
+            w: SCROLLBAR_WIDTH,
+            h: thumb_h,
+            dpi: self.bounds.dpi,
+        }
+    }
+
+    //scrolls so the thumb, grabbed at offset, follows the pointer
+    fn drag_thumb(&mut self, y: f32, offset: f32) {
+        let thumb = self.get_thumb();
+        let (_, max_y) = self.get_max_scroll();
+        let track = self.bounds.h - thumb.h;
+        if track > 0.0 {
+            let scroll_y = (y - offset - self.bounds.y) / track * max_y;
+            let scroll_x = self.scroll_x;
+            self.set_scroll(scroll_x, scroll_y.min(max_y).max(0.0));
+        }
+    }
+
+    //none for a caret that does not blink
+    pub fn set_caret_blink(&mut self, rate: Option<Duration>) {
+        self.caret_blink = rate;
+        self.stop_blink();
+        self.restart_blink();
+    }
+
+    pub fn get_caret_blink(&self) -> Option<Duration> {
+        self.caret_blink
+    }
+
+    //shows the caret and starts blinking it again, called on input. The
+    //running timer is only pushed back, not replaced.
+    fn restart_blink(&mut self) {
+        self.caret_on.store(true, Ordering::Relaxed);
+        if !self.focus || self.caret_blink.is_none() {
+            self.stop_blink();
+            return;
+        }
+        if let Some(id) = self.blink_timer {
+            if Manager::restart_timer(id) {
+                return;
+            }
+        }
+        if let Some(rate) = self.caret_blink {
+            let caret_on = self.caret_on.clone();
+            self.blink_timer = Some(Manager::set_interval(rate, move || {
+                let on = caret_on.load(Ordering::Relaxed);
+                caret_on.store(!on, Ordering::Relaxed);
+            }));
+        }
+    }
+
+    fn stop_blink(&mut self) {
+        if let Some(id) = self.blink_timer.take() {
+            Manager::clear_timer(id);
+        }
+    }
+
+    pub fn has_focus(&self) -> bool {
+        self.focus
+    }
+
+    pub(crate) fn chars(&self) -> &[char] {
+        &self.value
+    }
+
+    pub(crate) fn set_decorations(&mut self, decorations: Decorations) {
+        self.decorations = decorations;
+    }
+
+    //keeps a copy of every event fired, for the element this box is part of
+    pub(crate) fn set_relay(&mut self, relay: bool) {
+        self.relay = if relay { Some(vec![]) } else { None };
+    }
+
+    pub(crate) fn take_relayed(&mut self) -> Vec<Relayed> {
+        match self.relay {
+            Some(ref mut relay) => relay.drain(..).collect(),
+            None => vec![],
+        }
+    }
+
+    //runs the handler for e, keeping a copy of d when relaying
+    fn fire<T: Any + Clone + Send + Sync>(&mut self, e: ElementEvent, d: T) -> bool {
+        if let Some(ref mut relay) = self.relay {
+            relay.push((e.clone(), Box::new(d.clone())));
+        }
+        self.exec_handler(e, &d)
+    }
+
+    pub(crate) fn get_line_tops(&self) -> Vec<f32> {
+        self.cache.get_para_tops()
+    }
+
+    //from the last frame
+    pub fn get_caret_extent(&self, index: usize) -> Option<properties::Extent> {
+        self.cache.get_caret_extent(index)
+    }
+
+    //like typing, so it can be undone and goes through the validator
+    pub fn replace_range(&mut self, start: usize, end: usize, text: &str) -> bool {
+        let len = self.value.len();
+        let (start, end) = (start.min(len), end.min(len));
+        if !self.editable || start > end {
+            return false;
+        }
+        self.edit(start, end, text.chars().collect(), EditKind::Other)
+    }
+
+    pub fn get_cursor_index(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn set_cursor_index(&mut self, index: usize) {
+        self.cursor = index.min(self.value.len());
+        self.anchor = self.cursor;
+    }
+
+    //logical start and end of the selection, None when nothing is selected
+    pub fn get_selection(&self) -> Option<(usize, usize)> {
+        if self.anchor == self.cursor {
+            None
+        } else if self.anchor < self.cursor {
+            Some((self.anchor, self.cursor))
+        } else {
+            Some((self.cursor, self.anchor))
+        }
+    }
+
+    //selects from start to end, leaving the cursor at end
+    pub fn set_selection(&mut self, start: usize, end: usize) {
+        let len = self.value.len();
+        self.anchor = start.min(len);
+        self.cursor = end.min(len);
+        self.column = None;
+        self.last_edit = None;
+        self.follow_caret = true;
+    }
+
+    pub fn select_all(&mut self) {
+        self.set_selection(0, self.value.len());
+    }
+
+    pub fn get_selected_text(&self) -> String {
+        match self.get_selection() {
+            Some((start, end)) => self.value[start..end].iter().collect(),
+            None => String::new(),
+        }
+    }
+
+    //used for copy, cut and paste
+    pub fn set_clipboard(&mut self, clipboard: Box<Clipboard>) {
+        self.clipboard = clipboard;
+    }
+
+    //copying out of a password box is not allowed
+    pub fn copy(&mut self) -> bool {
+        if self.is_password || self.get_selection().is_none() {
+            return false;
+        }
+        let text = self.get_selected_text();
+        self.clipboard.set_contents(text);
+        true
+    }
+
+    pub fn cut(&mut self) -> bool {
+        if !self.editable || !self.copy() {
+            return false;
+        }
+        self.delete_selection()
+    }
+
+    pub fn paste(&mut self) -> bool {
+        if !self.editable {
+            return false;
+        }
+        let contents = match self.clipboard.get_contents() {
+            Some(contents) => contents,
+            None => return false,
+        };
+        let mut chars: Vec<char> = contents
+            .replace("\r\n", "\n")
+            .chars()
+            .map(|c| if c == '\r' { '\n' } else { c })
+            .filter(|c| !c.is_control() || *c == '\n' || *c == '\t')
+            .collect();
+        if self.singleline {
+            chars.retain(|c| *c != '\n');
+        }
+        let (start, end) = self.get_selection().unwrap_or((self.cursor, self.cursor));
+        self.edit(start, end, chars, EditKind::Other)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    //restores the cursor and selection the edit had
+    pub fn undo(&mut self) -> bool {
+        match self.undo_stack.pop() {
+            Some(state) => {
+                let current = self.snapshot();
+                self.redo_stack.push(current);
+                self.restore(state);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn redo(&mut self) -> bool {
+        match self.redo_stack.pop() {
+            Some(state) => {
+                let current = self.snapshot();
+                self.undo_stack.push(current);
+                self.restore(state);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn clear_history(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.last_edit = None;
+    }
+
+    fn snapshot(&self) -> EditState {
+        EditState {
+            value: self.value.clone(),
+            cursor: self.cursor,
+            anchor: self.anchor,
+        }
+    }
+
+    fn restore(&mut self, state: EditState) {
+        let old = mem::replace(&mut self.value, state.value);
+        self.cursor = state.cursor;
+        self.anchor = state.anchor;
+        self.column = None;
+        self.last_edit = None;
+        self.follow_caret = true;
+        self.drawn = 0;
+        self.validate();
+        let change = TextChange::diff(&old, &self.value);
+        self.fire(ElementEvent::Changed, change);
+    }
+
+    //saves the state for undo, unless the edit continues a burst of the same
+    //kind. A burst ends after a pause, and typing ends it at each new word.
+    fn record(&mut self, kind: EditKind, word_start: bool) {
+        let now = Instant::now();
+        if let Some((last, at)) = self.last_edit {
+            if kind != EditKind::Other
+                && kind == last
+                && now.duration_since(at) < UNDO_BURST_GAP
+                && !word_start
+            {
+                self.last_edit = Some((kind, now));
+                return;
+            }
+        }
+        let state = self.snapshot();
+        self.undo_stack.push(state);
+        if self.undo_stack.len() > UNDO_LIMIT {
+            self.undo_stack.remove(0);
+        }
+        self.redo_stack.clear();
+        self.last_edit = if kind == EditKind::Other {
+            None
+        } else {
+            Some((kind, now))
+        };
+    }
+
+    //replaces start..end with text and puts the cursor after it.
+    //all changes to the value by the user go through here.
+    fn edit(&mut self, start: usize, end: usize, mut text: Vec<char>, kind: EditKind) -> bool {
+        if let Some(ref filter) = self.filter {
+            text.retain(|c| filter(*c));
+        }
+        if let Some(max) = self.max_length {
+            let room = max.saturating_sub(self.value.len() - (end - start));
+            text.truncate(room);
+        }
+        if start == end && text.is_empty() {
+            return false;
+        }
+        let word_start = kind == EditKind::Insert
+            && start > 0
+            && self.value[start - 1].is_whitespace()
+            && text.first().map_or(false, |c| !c.is_whitespace());
+
+        //the range is exact for plain edits, a mask can move the chars after it
+        let (value, cursor, range) = if self.mask.is_empty() {
+            let mut value = self.value.clone();
+            let cursor = start + text.len();
+            value.splice(start..end, text);
+            (value, cursor, Some((end, cursor)))
+        } else {
+            match apply_mask(&self.mask, &self.value, start, end, &text) {
+                Some((value, cursor)) => (value, cursor, None),
+                None => return false,
+            }
+        };
+        if value == self.value {
+            return false;
+        }
+
+        let validation = match self.validator {
+            Some(ref v) => v(&value.iter().collect::<String>()),
+            None => Validation::Valid,
+        };
+        if validation == Validation::Reject {
+            return false;
+        }
+        self.invalid = validation == Validation::Invalid;
+
+        self.record(kind, word_start);
+        let old = mem::replace(&mut self.value, value);
+        self.cursor = cursor;
+        self.anchor = self.cursor;
+        self.column = None;
+        self.follow_caret = true;
+        self.drawn = 0;
+        let change = match range {
+            Some((old_end, new_end)) => TextChange::new(&old, &self.value, start, old_end, new_end),
+            None => TextChange::diff_from(&old, &self.value, start),
+        };
+        self.fire(ElementEvent::Changed, change);
+        true
+    }
+
+    //moves the cursor, keeping the anchor when extending a selection
+    fn move_cursor(&mut self, index: usize, extend: bool) {
+        self.cursor = index.min(self.value.len());
+        if !extend {
+            self.anchor = self.cursor;
+        }
+        self.column = None;
+        self.last_edit = None;
+        self.follow_caret = true;
+    }
+
+    //start of the previous or end of the next word
+    fn word_step(&self, forward: bool) -> usize {
+        let words = font::word_ranges(&self.value);
+        if forward {
+            words
+                .iter()
+                .find(|w| w.1 > self.cursor)
+                .map_or(self.value.len(), |w| w.1)
+        } else {
+            words
+                .iter()
+                .rev()
+                .find(|w| w.0 < self.cursor)
+                .map_or(0, |w| w.0)
+        }
+    }
+
+    //arrows, Home/End and PageUp/PageDown. Shift extends the selection,
+    //ctrl moves by words or to the start and end of the text.
+    fn navigate(&mut self, vkc: VirtualKeyCode, m: &properties::Modifiers) -> bool {
+        let line_h = self.props.get_size() as f32;
+        let page_h = self.bounds.h.max(line_h);
+        let index = match vkc {
+            VirtualKeyCode::Left | VirtualKeyCode::Right => {
+                let right = vkc == VirtualKeyCode::Right;
+                match self.get_selection() {
+                    Some((start, end)) if !m.shift && !m.ctrl => {
+                        if right {
+                            end
+                        } else {
+                            start
+                        }
+                    }
+                    _ => {
+                        if m.ctrl {
+                            let forward = right != self.cache.is_rtl_at(self.cursor);
+                            self.word_step(forward)
+                        } else {
+                            self.cache.get_caret_visual_step(self.cursor, right)
+                        }
+                    }
                 }
-                (ind,after)
             }
+            VirtualKeyCode::Up
+            | VirtualKeyCode::Down
+            | VirtualKeyCode::PageUp
+            | VirtualKeyCode::PageDown => {
+                let x = match self.column {
+                    Some(x) => x,
+                    None => self
+                        .cache
+                        .get_caret_extent(self.cursor)
+                        .map_or(self.bounds.x, |c| c.x),
+                };
+                let dy = match vkc {
+                    VirtualKeyCode::Up => -line_h,
+                    VirtualKeyCode::Down => line_h,
+                    VirtualKeyCode::PageUp => -page_h,
+                    _ => page_h,
+                };
+                let index = self.cache.get_caret_vertical(self.cursor, x, dy);
+                self.move_cursor(index, m.shift);
+                self.column = Some(x);
+                return true;
+            }
+            VirtualKeyCode::Home => {
+                if m.ctrl {
+                    0
+                } else {
+                    self.cache.get_line_range(self.cursor).0
+                }
+            }
+            VirtualKeyCode::End => {
+                if m.ctrl {
+                    self.value.len()
+                } else {
+                    self.cache.get_line_range(self.cursor).1
+                }
+            }
+            _ => return false,
+        };
+        self.move_cursor(index, m.shift);
+        true
+    }
+
+    //removes the selected text, returns false if there was none
+    fn delete_selection(&mut self) -> bool {
+        match self.get_selection() {
+            Some((start, end)) => self.edit(start, end, vec![], EditKind::Other),
+            None => false,
         }
     }
 
@@ -155,9 +909,13 @@ impl Element for TextBox {
         let family = self.props.get_family();
         let mut color = self.props.get_color();
         let mut bgcolor = self.props.get_bg_color();
+        let selection_bgcolor = self.props.get_selection_bg_color();
         let width = self.props.get_width();
         let height = self.props.get_height();
         let text_align = self.props.get_text_align();
+        let word_break = self.props.get_word_break();
+        let features = self.props.get_font_features();
+        let language = self.props.get_language();
 
         if self.hovering {
             color = self.props.get_hover_color();
@@ -169,6 +927,11 @@ impl Element for TextBox {
             bgcolor = self.props.get_focus_bg_color();
         }
 
+        if self.invalid {
+            color = self.props.get_error_color();
+            bgcolor = self.props.get_error_bg_color();
+        }
+
         if !self.enabled {
             color = self.props.get_disabled_color();
             bgcolor = self.props.get_disabled_bg_color();
@@ -180,18 +943,20 @@ impl Element for TextBox {
 
         let (_f_key, fi_key) = font_store.get_font_instance(&family, size as i32);
 
-        let val_str = "●".repeat(self.value.len()).chars().collect();
+        //a password is never shaped, so it stays out of the shape cache,
+        //the caret and scroll work on the dots which have one char per char
+        let val_str: Vec<char> = "●".repeat(self.value.len()).chars().collect();
 
-        let value = if !self.is_password {
+        let shown = if !self.is_password {
             &self.value
         } else {
             &val_str
         };
 
-        let value = if value.is_empty() {
+        let value = if shown.is_empty() {
             &self.placeholder
         } else {
-            &value
+            shown
         };
 
         let metrics = font_store.get_font_metrics(&family);
@@ -204,96 +969,183 @@ impl Element for TextBox {
             None => size,
         };
 
-        let mut paras = font::Paragraphs::from_chars(value);
-        paras.shape(
-            extent.x,
-            extent.y,
-            extent.w,
-            extent.h,
-            size,
-            baseline,
-            &family,
-            &text_align,
-        );
-        let _bounds = paras.get_extent();
-        let glyphs = paras.glyphs();
-
-        if !self.value.is_empty() {
-            self.cache = paras;
-        }
-
-        /*let (mut cursor_x, mut cursor_y, cursor_i) = (0.0, 0.0, self.cursor);
-
-        if cursor_i == 0 && self.cache.is_empty() {
-            cursor_x = _bounds.x;
-            cursor_y = _bounds.y + size;
-        } else if cursor_i == 0 && !self.cache.is_empty() {
-            cursor_x = (self.cache[0].0).0;
-            cursor_y = (self.cache[0].1).1;
-        } else if !self.cache.is_empty() {
-            cursor_x = (self.cache[cursor_i - 1].1).0;
-            cursor_y = (self.cache[cursor_i - 1].1).1;
-        }*/
-
-        let mut calc_w = _bounds.w;
-        let mut calc_h = _bounds.h;
+        let mut shape = |text: &Vec<char>, x: f32, y: f32, w: f32, align: &properties::Align| {
+            font_store.get_paragraphs(
+                text,
+                x,
+                y,
+                w,
+                extent.h,
+                size,
+                baseline,
+                &family,
+                align,
+                &word_break,
+                &features,
+                &language,
+            )
+        };
 
-        calc_w = match width {
-            properties::Unit::Extent => extent.w,
+        //single line boxes do not wrap, they scroll sideways instead
+        let mut box_w = match width {
+            properties::Unit::Extent | properties::Unit::Natural => extent.w,
             properties::Unit::Pixel(px) => px,
             properties::Unit::Stretch(s) => s * extent.w,
-            properties::Unit::Natural => calc_w,
         };
+        let gutter = self.decorations.gutter;
+        let mut align = text_align.clone();
+        let mut wrap_w = box_w - gutter;
+        if self.singleline {
+            align = properties::Align::Left;
+            wrap_w = NO_WRAP_WIDTH;
+        }
+        //shaped at 0, 0 and only moved into place at the end
+        let mut laid = shape(value, 0.0, 0.0, wrap_w, &align);
+        let mut content = laid.get_extent();
+        if let properties::Unit::Natural = width {
+            box_w = content.w + gutter;
+        }
+        if self.singleline && content.w <= box_w - gutter {
+            align = text_align.clone();
+            wrap_w = box_w - gutter;
+            laid = shape(value, 0.0, 0.0, wrap_w, &align);
+        }
 
-        calc_h = match height {
+        let box_h = match height {
             properties::Unit::Extent => extent.h,
             properties::Unit::Pixel(px) => px,
             properties::Unit::Stretch(s) => s * extent.h,
-            properties::Unit::Natural => calc_h,
+            properties::Unit::Natural => content.h,
+        };
+
+        //make room for the scrollbar when the text does not fit
+        let bar = content.h > box_h && box_w - gutter > SCROLLBAR_WIDTH;
+        if bar && !self.singleline {
+            wrap_w = box_w - gutter - SCROLLBAR_WIDTH;
+            laid = shape(value, 0.0, 0.0, wrap_w, &align);
+            content = laid.get_extent();
+        }
+        let view_x = extent.x + gutter;
+        let view_w = if bar {
+            box_w - gutter - SCROLLBAR_WIDTH
+        } else {
+            box_w - gutter
         };
 
         self.bounds = properties::Extent {
             x: extent.x,
             y: extent.y,
-            w: calc_w,
-            h: calc_h,
+            w: box_w,
+            h: box_h,
             dpi: extent.dpi,
         };
+        self.content = content.clone();
+        self.view_w = view_w;
+
+        //the cursor and selection work on the value, not the placeholder
+        let caret_laid = if shown.is_empty() {
+            shape(shown, 0.0, 0.0, wrap_w, &align)
+        } else {
+            laid.clone()
+        };
+
+        //keep the caret in view after it moved
+        if self.follow_caret {
+            self.follow_caret = false;
+            let caret = caret_laid.get_caret_extent(self.cursor);
+            if let Some(c) = caret {
+                if c.y < self.scroll_y {
+                    self.scroll_y = c.y;
+                } else if c.y + c.h > self.scroll_y + box_h {
+                    self.scroll_y = c.y + c.h - box_h;
+                }
+                if c.x < self.scroll_x {
+                    self.scroll_x = c.x;
+                } else if c.x + 1.0 > self.scroll_x + view_w {
+                    self.scroll_x = c.x + 1.0 - view_w;
+                }
+            }
+        }
+        let (max_x, max_y) = self.get_max_scroll();
+        self.scroll_x = self.scroll_x.min(max_x).max(0.0);
+        self.scroll_y = self.scroll_y.min(max_y).max(0.0);
 
-        let mut info = LayoutPrimitiveInfo::new(LayoutRect::new(
-            LayoutPoint::new(extent.x, extent.y),
-            LayoutSize::new(self.bounds.w, self.bounds.h),
-        ));
+        let text_x = view_x - self.scroll_x;
+        let text_y = extent.y - self.scroll_y;
+        let glyphs = laid.moved_to(text_x, text_y).glyphs();
+        self.cache = caret_laid.moved_to(text_x, text_y);
+
+        let mut info = LayoutPrimitiveInfo::new((extent.x, extent.y).by(box_w, box_h));
         info.tag = Some((_id, 0));
         builder.push_rect(&info, bgcolor);
 
-        let info = LayoutPrimitiveInfo::new(LayoutRect::new(
-            LayoutPoint::new(extent.x, extent.y),
-            LayoutSize::new(self.bounds.w, self.bounds.h),
-        ));
-        builder.push_text(&info, &glyphs, fi_key, color, Some(GlyphOptions::default()));
+        let clip = builder.define_clip((view_x, extent.y).by(view_w, box_h), vec![], None);
+        builder.push_clip_id(clip);
+
+        if let Some(line_color) = self.decorations.line_color {
+            if let Some(c) = self.cache.get_caret_extent(self.cursor) {
+                let info = LayoutPrimitiveInfo::new((view_x, c.y).by(view_w, c.h));
+                builder.push_rect(&info, line_color);
+            }
+        }
+
+        for (index, mark_color) in self.decorations.marks.iter() {
+            for r in self.cache.get_selection_extents(*index, index + 1) {
+                let info = LayoutPrimitiveInfo::new((r.x, r.y).by(r.w, r.h));
+                builder.push_rect(&info, *mark_color);
+            }
+        }
+
+        if let Some((start, end)) = self.get_selection() {
+            for r in self.cache.get_selection_extents(start, end) {
+                let info = LayoutPrimitiveInfo::new((r.x, r.y).by(r.w, r.h));
+                builder.push_rect(&info, selection_bgcolor);
+            }
+        }
+
+        let info = LayoutPrimitiveInfo::new((view_x, extent.y).by(view_w, box_h));
+        if self.decorations.styles.is_empty() || self.value.is_empty() {
+            builder.push_text(&info, &glyphs, fi_key, color, Some(GlyphOptions::default()));
+        } else {
+            //one text run for every stretch of chars sharing a colour
+            let mut colors = vec![color; self.value.len()];
+            for style in self.decorations.styles.iter() {
+                let end = style.end.min(colors.len());
+                for c in colors[style.start.min(end)..end].iter_mut() {
+                    *c = style.color;
+                }
+            }
+            let mut start = 0;
+            for i in 1..=colors.len() {
+                if i == colors.len() || colors[i] != colors[start] {
+                    let glyphs = self.cache.glyphs_in_range(start, i);
+                    builder.push_text(&info, &glyphs, fi_key, colors[start], Some(GlyphOptions::default()));
+                    start = i;
+                }
+            }
+        }
 
         //add the cursor
-        if self.focus && self.enabled && self.editable {
-            let ch = self.cache.get_char_at_index(self.cursor_index);
-            match ch {
-                Some(ref ch) => {
-                    let pos = ch.get_position();
-                    let mut info = LayoutPrimitiveInfo::new(LayoutRect::new(
-                        LayoutPoint::new(pos.x, pos.y - size),
-                        LayoutSize::new(1.0, size),
-                    ));
-                    if self.cursor_after {
-                        info = LayoutPrimitiveInfo::new(LayoutRect::new(
-                            LayoutPoint::new(pos.x + ch.get_metric().advance.x, pos.y - size),
-                            LayoutSize::new(1.0, size),
-                        ));
-                    }
-                    builder.push_rect(&info, color);
-                },
-                None => (),
+        if self.focus && self.enabled && self.editable && self.caret_on.load(Ordering::Relaxed) {
+            if let Some(c) = self.cache.get_caret_extent(self.cursor) {
+                let info = LayoutPrimitiveInfo::new((c.x, c.y).by(1.0, c.h));
+                builder.push_rect(&info, color);
             }
         }
+
+        builder.pop_clip_id();
+
+        //vertical scrollbar, the track is tagged so it can be dragged
+        if bar {
+            let track_x = view_x + view_w;
+            let mut info = LayoutPrimitiveInfo::new((track_x, extent.y).by(SCROLLBAR_WIDTH, box_h));
+            info.tag = Some((_id, SCROLLBAR_TAG));
+            builder.push_rect(&info, bgcolor);
+
+            let thumb = self.get_thumb();
+            let info = LayoutPrimitiveInfo::new((thumb.x, thumb.y).by(thumb.w, thumb.h));
+            builder.push_rect(&info, ColorF::new(color.r, color.g, color.b, 0.4));
+        }
     }
 
     fn get_bounds(&self) -> properties::Extent {
@@ -302,134 +1154,74 @@ impl Element for TextBox {
 
     fn on_primitive_event(&mut self, ext_ids: &[ItemTag], e: PrimitiveEvent) -> bool {
         let mut handled = false;
+        let input = match e {
+            PrimitiveEvent::KeyInput(..) | PrimitiveEvent::Char(_) | PrimitiveEvent::Button(..) => true,
+            _ => false,
+        };
         match e {
-            /*PrimitiveEvent::Char(mut c) => {
-            if self.focus && self.enabled && self.editable {
-                if c == '\x08' {
-                    let mut l = self.cursor;
-                    if l > 0 {
-                        //l -= 1;
-                        /*while !self.value.is_char_boundary(l) && l > 0 {
-                            l -= 1;
+            PrimitiveEvent::KeyInput(vkc, _sc, s, m) => {
+                if self.focus && self.enabled && s == properties::ButtonState::Pressed {
+                    match vkc {
+                        //ctrl+z, ctrl+shift+z and ctrl+y
+                        Some(VirtualKeyCode::Z) if m.ctrl && self.editable => {
+                            if m.shift {
+                                self.redo();
+                            } else {
+                                self.undo();
+                            }
+                            handled = true;
                         }
-                        self.value =
-                            //format!("{}{}", &self.value[0..l], &self.value[self.cursor..]);
-                        self.cursor = l;*/
-            self.value.remove(l);
-            self.cursor -=1;
-            }
-            } else if c == '\u{3}' {
-            if !self.is_password {
-            let mut ctx: ClipboardContext = ClipboardProvider::new().unwrap();
-            ctx.set_contents(self.value.clone()).unwrap();
-            }
-            } else if c == '\u{16}' {
-            let mut ctx: ClipboardContext = ClipboardProvider::new().unwrap();
-            let vstr = ctx.get_contents().unwrap();
-            self.value = format!(
-            "{}{}{}",
-            &self.value[0..self.cursor],
-            &vstr[0..],
-            &self.value[self.cursor..]
-            );
-            self.cursor += vstr.len();
-            } else {
-            if c == '\r' {
-            c = '\n';
-            }
-            let mut tmp_chars = self.value.chars().collect_vec();
-
-            if self.singleline && c == '\n' {
-            //do not save the new line
-            } else {
-            /*if self.cursor == 0 {
-                let mut newstr = format!("{}", c);
-                newstr.push_str(&self.value[0..]);
-                self.value = newstr;
-            } else if self.cursor < tmp_chars.len() {
-                tmp_chars.insert(self.cursor,c);
-                self.value = tmp_chars.into_iter().collect();
-            } else {
-                self.value.push(c);
-            }*/
-            if self.cursor == tmp_chars.len() {
-            self.value.push(c);
-            } else {
-            tmp_chars.insert(self.cursor,c);
-            self.value = tmp_chars.into_iter().collect();
-            }
-
-            self.cursor += 1;
-            }
-            }
-            handled = true;
-            }
-            },
-            PrimitiveEvent::KeyInput(vkc, _sc, s, _m) => match vkc {
-            Some(VirtualKeyCode::Right) => {
-            if self.cursor < self.value.len() && s == properties::ButtonState::Pressed {
-            self.cursor += 1;
-            }
-            }
-            Some(VirtualKeyCode::Left) => {
-            if self.cursor > 0 && s == properties::ButtonState::Pressed {
-            self.cursor -= 1;
-            }
+                        Some(VirtualKeyCode::Y) if m.ctrl && self.editable => {
+                            self.redo();
+                            handled = true;
+                        }
+                        Some(vkc) => handled = self.navigate(vkc, &m),
+                        None => (),
+                    }
+                }
             }
-            _ => (),
-            },*/
-            PrimitiveEvent::KeyInput(vkc, _sc, _s, _m) => match vkc {
-                _ => (),
-            },
             PrimitiveEvent::Char(mut c) => {
                 if self.focus && self.enabled && self.editable {
+                    self.column = None;
                     if c == '\x08' { //backspace
-                        let len = self.value.len();
-                        //if len > 0 and after is true then should delete from index
-                        if len > 0 && self.cursor_after {
-                            self.value.remove(self.cursor_index);
-                            if self.cursor_index == 0 {
-                                self.cursor_after = false;
-                            } else {
-                                self.cursor_index -= 1;
-                            }
-                        }
-                        // if len > 0 and after is false
-                        else if len > 0 && !self.cursor_after {
-                            if self.cursor_index > 0 {
-                                self.value.remove(self.cursor_index - 1);
-                                self.cursor_index -= 1;
-                            }
+                        if !self.delete_selection() && self.cursor > 0 {
+                            //in a mask the literals before the caret go with the slot before them
+                            let end = self.cursor;
+                            let start = slot_before(&self.mask, end).unwrap_or(end - 1);
+                            self.edit(start, end, vec![], EditKind::Delete);
                         }
                     } else if c == '\u{7f}' { //delete key
-                        let len = self.value.len();
-                        if len > self.cursor_index+1 && self.cursor_after {
-                            self.value.remove(self.cursor_index+1);
-                        }
-                        else if len > self.cursor_index && !self.cursor_after{
-                            self.value.remove(self.cursor_index);
-                            let len = self.value.len();
-                            if self.cursor_index > 0 && self.cursor_index == len && len > 0 {
-                                self.cursor_index -=1;
-                                self.cursor_after = true;
-                            }
+                        if !self.delete_selection() && self.cursor < self.value.len() {
+                            let start = self.cursor;
+                            let end = slot_after(&self.mask, start).map_or(start + 1, |s| s + 1);
+                            self.edit(start, end.min(self.value.len()), vec![], EditKind::Delete);
                         }
-
-                    } else if c == '\u{3}' {
-
-                    } else if c == '\u{16}' {
-
+                    } else if c == '\u{1}' { //ctrl+a
+                        self.select_all();
+                    } else if c == '\u{3}' { //ctrl+c
+                        self.copy();
+                    } else if c == '\u{18}' { //ctrl+x
+                        self.cut();
+                    } else if c == '\u{16}' { //ctrl+v
+                        self.paste();
+                    } else if self.singleline && (c == '\r' || c == '\n') {
+                        let submit = SubmitEvent {
+                            value: self.get_value(),
+                        };
+                        self.fire(ElementEvent::Submit, submit);
                     } else {
                         if c == '\r' {
                             c = '\n';
                         }
-                        if self.cursor.is_some() {
-                            if self.cursor_after {
-                                self.value.insert(self.cursor_index + 1,c);
-                            } else {
-                                self.value.insert(self.cursor_index,c);
-                            }
-                            self.cursor_index += 1;
+                        let skip = c.is_control() && c != '\n' && c != '\t';
+                        if !skip {
+                            //replacing a selection or a new line starts a new undo step
+                            let (start, end, kind) = match self.get_selection() {
+                                Some((start, end)) => (start, end, EditKind::Other),
+                                None if c == '\n' => (self.cursor, self.cursor, EditKind::Other),
+                                None => (self.cursor, self.cursor, EditKind::Insert),
+                            };
+                            self.edit(start, end, vec![c], kind);
                         }
                     }
                     handled = true;
@@ -438,40 +1230,110 @@ impl Element for TextBox {
             PrimitiveEvent::SetFocus(f) => {
                 if self.enabled && self.focus != f {
                     self.focus = f;
-                    handled = self.exec_handler(ElementEvent::FocusChange, &f);
+                    if f {
+                        self.restart_blink();
+                    } else {
+                        self.selecting = false;
+                        self.stop_blink();
+                    }
+                    let focus = FocusEvent { old: !f, new: f };
+                    handled = self.fire(ElementEvent::FocusChange, focus);
                 }
             }
-            PrimitiveEvent::Button(p, b, s, m) => {
-                if !ext_ids.is_empty()
-                && ext_ids[0].0 == self.ext_id
-                && b == properties::Button::Left
-                && s == properties::ButtonState::Released
-                {
-                    let tmp = self.cache.get_char_at_pos(&p, &self.value);
-                    if tmp.is_some() {
-                        self.cursor = Some((tmp.unwrap(),p.clone()));
-                        let tmp = self.get_cursor_index();
-                        self.cursor_index = tmp.0;
-                        self.cursor_after = tmp.1;
-                        println!("Clicked at ind[{}] {:?} ... appears after? {}",self.cursor_index, self.cursor, self.cursor_after);
+            PrimitiveEvent::Button(p, b, s, m, clicks) => {
+                let on_self = !ext_ids.is_empty() && ext_ids[0].0 == self.ext_id;
+                let on_bar = on_self && ext_ids[0].1 == SCROLLBAR_TAG;
+                if b == properties::Button::Left {
+                    if s == properties::ButtonState::Pressed {
+                        if on_bar {
+                            //grab the thumb, or jump to where the track was clicked
+                            let thumb = self.get_thumb();
+                            if p.y < thumb.y || p.y > thumb.y + thumb.h {
+                                self.drag_thumb(p.y, thumb.h / 2.0);
+                            }
+                            self.thumb_drag = Some(p.y - self.get_thumb().y);
+                            handled = true;
+                        } else if on_self && self.enabled {
+                            //shift+click extends the selection, a double click
+                            //selects a word and a triple click the whole line
+                            let index = self.cache.get_caret_at_pos(&p);
+                            match clicks {
+                                2 => {
+                                    let (start, end) = font::word_at(&self.value, index);
+                                    self.set_selection(start, end);
+                                }
+                                3 => {
+                                    let (start, end) = font::line_at(&self.value, index);
+                                    self.set_selection(start, end);
+                                }
+                                _ => self.move_cursor(index, m.shift),
+                            }
+                            self.selecting = clicks < 2;
+                            handled = true;
+                        }
+                    } else {
+                        self.selecting = false;
+                        self.thumb_drag = None;
+                        if on_self {
+                            let click = ClickEvent {
+                                position: Some(p),
+                                button: b,
+                                modifiers: m,
+                                clicks,
+                            };
+                            handled = self.fire(ElementEvent::Clicked, click);
+                        }
                     }
-                    handled = self.exec_handler(ElementEvent::Clicked, &m);
+                }
+            }
+            PrimitiveEvent::CursorMoved(p) => {
+                if let Some(offset) = self.thumb_drag {
+                    self.drag_thumb(p.y, offset);
+                    handled = true;
+                } else if self.selecting {
+                    let index = self.cache.get_caret_at_pos(&p);
+                    self.move_cursor(index, true);
+                    handled = true;
+                }
+            }
+            PrimitiveEvent::Wheel(_p, delta, m) => {
+                let on_self = !ext_ids.is_empty() && ext_ids[0].0 == self.ext_id;
+                if on_self {
+                    //shift scrolls a vertical wheel sideways, a trackpad that
+                    //already moves sideways is left alone. Only a wheel that
+                    //moved the text is handled, so at the ends it scrolls
+                    //whatever is around the textbox.
+                    let (mut dx, mut dy) = delta.to_pixels(Manager::get_wheel_line_height());
+                    if m.shift && dx == 0.0 {
+                        dx = dy;
+                        dy = 0.0;
+                    }
+                    let before = self.get_scroll();
+                    self.scroll_by(-dx, -dy);
+                    handled = self.get_scroll() != before;
                 }
             }
             PrimitiveEvent::HoverBegin(n_tags) => {
                 let matched = n_tags.iter().find(|x| x.0 == self.ext_id);
-                if matched.is_some() {
+                if matched.is_some() && !self.hovering {
                     self.hovering = true;
+                    let hover = HoverEvent { tags: n_tags };
+                    handled = self.fire(ElementEvent::HoverBegin, hover);
                 }
             }
             PrimitiveEvent::HoverEnd(o_tags) => {
                 let matched = o_tags.iter().find(|x| x.0 == self.ext_id);
-                if matched.is_some() {
+                if matched.is_some() && self.hovering {
                     self.hovering = false;
+                    let hover = HoverEvent { tags: o_tags };
+                    handled = self.fire(ElementEvent::HoverEnd, hover);
                 }
             }
             _ => (),
         }
+        if input && self.focus {
+            self.restart_blink();
+        }
         handled
     }
 
@@ -494,14 +1356,410 @@ impl Element for TextBox {
     fn as_any_mut(&mut self) -> &mut Any {
         self
     }
+    fn is_focusable(&self) -> bool {
+        true
+    }
+    fn wants_tab(&self) -> bool {
+        self.accepts_tab
+    }
+    //the editing keys stay with the textbox while it has focus
+    fn overrides_shortcut(&self, key: VirtualKeyCode, modifiers: &properties::Modifiers) -> bool {
+        if modifiers.alt || modifiers.logo {
+            return false;
+        }
+        match key {
+            VirtualKeyCode::A
+            | VirtualKeyCode::C
+            | VirtualKeyCode::X
+            | VirtualKeyCode::V
+            | VirtualKeyCode::Z
+            | VirtualKeyCode::Y => modifiers.ctrl,
+            VirtualKeyCode::Left
+            | VirtualKeyCode::Right
+            | VirtualKeyCode::Up
+            | VirtualKeyCode::Down
+            | VirtualKeyCode::PageUp
+            | VirtualKeyCode::PageDown
+            | VirtualKeyCode::Home
+            | VirtualKeyCode::End
+            | VirtualKeyCode::Back
+            | VirtualKeyCode::Delete
+            | VirtualKeyCode::Return => true,
+            VirtualKeyCode::Tab => self.accepts_tab,
+            _ => false,
+        }
+    }
+    fn as_can_disable(&self) -> Option<&CanDisable> {
+        Some(self)
+    }
+}
+
+impl Drop for TextBox {
+    fn drop(&mut self) {
+        self.stop_blink();
+    }
 }
 
 impl CanDisable for TextBox {
     fn set_enabled(&mut self, value: bool) {
         self.enabled = value;
+        Manager::tree_changed();
     }
 
     fn get_enabled(&self) -> bool {
         self.enabled
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gui::clipboard::MemoryClipboard;
+    use std::sync::Mutex;
+
+    //a focused box with the cursor at the end
+    fn text_box(value: &str) -> TextBox {
+        let mut tb = TextBox::new(value.to_owned());
+        tb.focus = true;
+        tb.set_cursor_index(value.chars().count());
+        tb
+    }
+
+    fn type_str(tb: &mut TextBox, s: &str) {
+        for c in s.chars() {
+            tb.on_primitive_event(&[], PrimitiveEvent::Char(c));
+        }
+    }
+
+    #[test]
+    fn mask_fills_slots() {
+        let mut tb = text_box("");
+        tb.set_mask("(999) 999-9999".to_owned());
+        type_str(&mut tb, "5551234");
+        assert_eq!(tb.get_value(), "(555) 123-4");
+        assert_eq!(tb.get_cursor_index(), 11);
+    }
+
+    #[test]
+    fn mask_keeps_chars_equal_to_literals() {
+        let mut tb = text_box("");
+        tb.set_mask("+1 (999) 999-9999".to_owned());
+        type_str(&mut tb, "1");
+        assert_eq!(tb.get_value(), "+1 (1");
+        type_str(&mut tb, "1");
+        assert_eq!(tb.get_value(), "+1 (11");
+    }
+
+    #[test]
+    fn mask_takes_typed_literals() {
+        let mut tb = text_box("");
+        tb.set_mask("+1 (999) 999-9999".to_owned());
+        type_str(&mut tb, "+1 (555) 1");
+        assert_eq!(tb.get_value(), "+1 (555) 1");
+        assert!(!tb.replace_range(10, 10, "x"));
+    }
+
+    #[test]
+    fn mask_backspace_skips_literals() {
+        let mut tb = text_box("");
+        tb.set_mask("(999) 999-9999".to_owned());
+        type_str(&mut tb, "555123");
+        tb.set_cursor_index(6);
+        type_str(&mut tb, "\x08");
+        assert_eq!(tb.get_value(), "(551) 23");
+        assert_eq!(tb.get_cursor_index(), 3);
+    }
+
+    #[test]
+    fn mask_delete_skips_literals() {
+        let mut tb = text_box("");
+        tb.set_mask("(999) 999-9999".to_owned());
+        type_str(&mut tb, "555123");
+        tb.set_cursor_index(4);
+        type_str(&mut tb, "\u{7f}");
+        assert_eq!(tb.get_value(), "(555) 23");
+        assert_eq!(tb.get_cursor_index(), 4);
+    }
+
+    fn with_clipboard(value: &str) -> TextBox {
+        let mut tb = text_box(value);
+        tb.set_clipboard(Box::new(MemoryClipboard::new()));
+        tb
+    }
+
+    #[test]
+    fn copy_keeps_value() {
+        let mut tb = with_clipboard("hello world");
+        tb.set_selection(0, 5);
+        assert!(tb.copy());
+        assert_eq!(tb.clipboard.get_contents(), Some("hello".to_owned()));
+        assert_eq!(tb.get_value(), "hello world");
+    }
+
+    #[test]
+    fn copy_needs_selection() {
+        let mut tb = with_clipboard("hello");
+        assert!(!tb.copy());
+        assert_eq!(tb.clipboard.get_contents(), None);
+    }
+
+    #[test]
+    fn cut_removes_selection() {
+        let mut tb = with_clipboard("hello world");
+        tb.set_selection(5, 11);
+        assert!(tb.cut());
+        assert_eq!(tb.clipboard.get_contents(), Some(" world".to_owned()));
+        assert_eq!(tb.get_value(), "hello");
+        assert_eq!(tb.get_cursor_index(), 5);
+    }
+
+    #[test]
+    fn paste_replaces_selection() {
+        let mut tb = with_clipboard("hello world");
+        tb.clipboard.set_contents("there".to_owned());
+        tb.set_selection(6, 11);
+        assert!(tb.paste());
+        assert_eq!(tb.get_value(), "hello there");
+        assert_eq!(tb.get_cursor_index(), 11);
+        assert_eq!(tb.get_selection(), None);
+    }
+
+    #[test]
+    fn password_blocks_copy_and_cut() {
+        let mut tb = with_clipboard("secret");
+        tb.set_is_password(true);
+        tb.select_all();
+        assert!(!tb.copy());
+        assert!(!tb.cut());
+        assert_eq!(tb.clipboard.get_contents(), None);
+        assert_eq!(tb.get_value(), "secret");
+    }
+
+    #[test]
+    fn paste_into_singleline_strips_newlines() {
+        let mut tb = with_clipboard("");
+        tb.set_singleline(true);
+        tb.clipboard.set_contents("one\r\ntwo\nthree".to_owned());
+        assert!(tb.paste());
+        assert_eq!(tb.get_value(), "onetwothree");
+    }
+
+    #[test]
+    fn paste_into_multiline_keeps_newlines() {
+        let mut tb = with_clipboard("");
+        tb.clipboard.set_contents("one\r\ntwo".to_owned());
+        assert!(tb.paste());
+        assert_eq!(tb.get_value(), "one\ntwo");
+    }
+
+    #[test]
+    fn typing_burst_is_one_undo_step() {
+        let mut tb = text_box("");
+        type_str(&mut tb, "abc");
+        assert_eq!(tb.undo_stack.len(), 1);
+        assert!(tb.undo());
+        assert_eq!(tb.get_value(), "");
+        assert!(!tb.can_undo());
+    }
+
+    #[test]
+    fn other_kind_starts_new_step() {
+        let mut tb = text_box("");
+        type_str(&mut tb, "abc");
+        type_str(&mut tb, "\x08\x08");
+        type_str(&mut tb, "xy");
+        assert_eq!(tb.get_value(), "axy");
+        assert!(tb.undo());
+        assert_eq!(tb.get_value(), "a");
+        assert!(tb.undo());
+        assert_eq!(tb.get_value(), "abc");
+        assert!(tb.undo());
+        assert_eq!(tb.get_value(), "");
+    }
+
+    #[test]
+    fn a_new_word_starts_a_new_step() {
+        let mut tb = text_box("");
+        type_str(&mut tb, "one two  three");
+        assert_eq!(tb.undo_stack.len(), 3);
+        assert!(tb.undo());
+        assert_eq!(tb.get_value(), "one two  ");
+        assert!(tb.undo());
+        assert_eq!(tb.get_value(), "one ");
+    }
+
+    #[test]
+    fn a_pause_ends_a_burst() {
+        let mut tb = text_box("");
+        type_str(&mut tb, "ab");
+        //as if the last key was typed a while ago
+        if let Some((kind, at)) = tb.last_edit {
+            tb.last_edit = Some((kind, at - UNDO_BURST_GAP));
+        }
+        type_str(&mut tb, "c");
+        assert_eq!(tb.undo_stack.len(), 2);
+        assert!(tb.undo());
+        assert_eq!(tb.get_value(), "ab");
+    }
+
+    #[test]
+    fn moving_the_cursor_ends_a_burst() {
+        let mut tb = text_box("");
+        type_str(&mut tb, "ab");
+        tb.move_cursor(1, false);
+        type_str(&mut tb, "x");
+        assert_eq!(tb.get_value(), "axb");
+        assert!(tb.undo());
+        assert_eq!(tb.get_value(), "ab");
+    }
+
+    #[test]
+    fn undo_and_redo_restore_cursor_and_selection() {
+        let mut tb = text_box("hello world");
+        tb.set_selection(0, 5);
+        type_str(&mut tb, "J");
+        assert_eq!(tb.get_value(), "J world");
+        assert_eq!(tb.get_cursor_index(), 1);
+
+        assert!(tb.undo());
+        assert_eq!(tb.get_value(), "hello world");
+        assert_eq!(tb.get_selection(), Some((0, 5)));
+        assert_eq!(tb.get_cursor_index(), 5);
+
+        assert!(tb.redo());
+        assert_eq!(tb.get_value(), "J world");
+        assert_eq!(tb.get_selection(), None);
+        assert_eq!(tb.get_cursor_index(), 1);
+        assert!(!tb.can_redo());
+    }
+
+    #[test]
+    fn edit_clears_redo() {
+        let mut tb = text_box("");
+        type_str(&mut tb, "a");
+        assert!(tb.undo());
+        assert!(tb.can_redo());
+        type_str(&mut tb, "b");
+        assert!(!tb.can_redo());
+    }
+
+    #[test]
+    fn history_is_trimmed_at_limit() {
+        let mut tb = text_box("");
+        for i in 0..UNDO_LIMIT + 10 {
+            let end = tb.get_value().len();
+            assert!(tb.replace_range(end, end, &(i % 10).to_string()));
+        }
+        assert_eq!(tb.undo_stack.len(), UNDO_LIMIT);
+        while tb.undo() {}
+        //the oldest steps were dropped, undo stops after the first ten edits
+        assert_eq!(tb.get_value(), "0123456789");
+    }
+
+    fn change(old: &str, new: &str) -> (usize, usize, usize) {
+        let old: Vec<char> = old.chars().collect();
+        let new: Vec<char> = new.chars().collect();
+        let c = TextChange::diff(&old, &new);
+        (c.start, c.old_end, c.new_end)
+    }
+
+    #[test]
+    fn change_insert() {
+        assert_eq!(change("hello", "hel-lo"), (3, 3, 4));
+        assert_eq!(change("", "abc"), (0, 0, 3));
+        assert_eq!(change("ab", "abc"), (2, 2, 3));
+    }
+
+    #[test]
+    fn change_delete() {
+        assert_eq!(change("hello", "helo"), (3, 4, 3));
+        assert_eq!(change("abc", ""), (0, 3, 0));
+    }
+
+    #[test]
+    fn change_replace_in_middle() {
+        assert_eq!(change("hello world", "hello there world"), (6, 6, 12));
+        assert_eq!(change("one two three", "one 2 three"), (4, 7, 5));
+    }
+
+    #[test]
+    fn change_repeated_chars() {
+        //the common suffix never overlaps the common prefix
+        assert_eq!(change("aaa", "aaaa"), (3, 3, 4));
+        assert_eq!(change("aaaa", "aa"), (2, 4, 2));
+    }
+
+    //records the payloads of an event kind, as a user handler would
+    fn record_event<T: Any + Clone>(tb: &mut TextBox, e: ElementEvent) -> Arc<Mutex<Vec<T>>> {
+        let seen = Arc::new(Mutex::new(vec![]));
+        let out = seen.clone();
+        tb.set_handler(
+            e,
+            EventFn::new(Arc::new(Mutex::new(move |_: &mut Element, d: &Any| {
+                if let Some(d) = d.downcast_ref::<T>() {
+                    out.lock().unwrap().push(d.clone());
+                }
+                true
+            }))),
+        );
+        seen
+    }
+
+    fn ranges(seen: &Arc<Mutex<Vec<TextChange>>>) -> Vec<(usize, usize, usize)> {
+        seen.lock()
+            .unwrap()
+            .iter()
+            .map(|c| (c.start, c.old_end, c.new_end))
+            .collect()
+    }
+
+    #[test]
+    fn changed_reports_the_edited_range() {
+        let mut tb = text_box("aa");
+        let seen = record_event::<TextChange>(&mut tb, ElementEvent::Changed);
+        tb.set_cursor_index(0);
+        type_str(&mut tb, "a");
+        assert_eq!(ranges(&seen), vec![(0, 0, 1)]);
+        assert_eq!(seen.lock().unwrap()[0].new_value, "aaa");
+    }
+
+    #[test]
+    fn changed_reports_delete_in_a_run() {
+        let mut tb = text_box("xaaay");
+        let seen = record_event::<TextChange>(&mut tb, ElementEvent::Changed);
+        tb.set_cursor_index(2);
+        type_str(&mut tb, "\u{8}");
+        assert_eq!(tb.get_value(), "xaay");
+        assert_eq!(ranges(&seen), vec![(1, 2, 1)]);
+    }
+
+    #[test]
+    fn changed_is_fired_for_undo() {
+        let mut tb = text_box("ab");
+        type_str(&mut tb, "c");
+        let seen = record_event::<TextChange>(&mut tb, ElementEvent::Changed);
+        assert!(tb.undo());
+        assert_eq!(ranges(&seen), vec![(2, 3, 2)]);
+    }
+
+    #[test]
+    fn submit_is_fired_on_enter() {
+        let mut tb = text_box("query");
+        tb.set_singleline(true);
+        let seen = record_event::<SubmitEvent>(&mut tb, ElementEvent::Submit);
+        let changed = record_event::<TextChange>(&mut tb, ElementEvent::Changed);
+        type_str(&mut tb, "\r");
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 1);
+        assert_eq!(seen[0].value, "query");
+        assert!(changed.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn change_nothing() {
+        assert_eq!(change("same", "same"), (4, 4, 4));
+        let c = TextChange::diff(&[], &[]);
+        assert_eq!((c.start, c.old_end, c.new_end), (0, 0, 0));
+        assert_eq!(c.old_value, c.new_value);
+    }
+}
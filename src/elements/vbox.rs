@@ -6,6 +6,7 @@ use webrender::api::*;
 use crate::elements::element::*;
 use crate::gui::font;
 use crate::gui::properties;
+use crate::gui::window::Manager;
 use crate::util::*;
 
 pub struct VBox {
@@ -279,6 +280,16 @@ impl Element for VBox {
                         _child_elm.on_primitive_event(&[], PrimitiveEvent::SetFocus(false));
                     }
                 }
+                //the window dispatches these along the event path. They are
+                //only passed on when nothing was targeted, or for the tags of
+                //children the window could not find (no get_children)
+                (PrimitiveEvent::Button(..), _)
+                | (PrimitiveEvent::Char(_), _)
+                | (PrimitiveEvent::KeyInput(..), _)
+                | (PrimitiveEvent::CursorMoved(_), _)
+                | (PrimitiveEvent::Wheel(..), _)
+                | (PrimitiveEvent::Touch(..), _)
+                    if ext_ids.len() == 1 => {}
                 (PrimitiveEvent::Char(_c), Ok(ref mut _child_elm)) => {
                     handled = _child_elm.on_primitive_event(&[], e.clone());
                     if handled {
@@ -309,8 +320,14 @@ impl Element for VBox {
         // if none of the children handled the event
         // see if you can handle it here
         if !handled {
-            if let PrimitiveEvent::Button(_p, _b, _s, m) = e {
-                handled = self.exec_handler(ElementEvent::Clicked, &m);
+            if let PrimitiveEvent::Button(p, b, _s, m, clicks) = e {
+                let click = ClickEvent {
+                    position: Some(p),
+                    button: b,
+                    modifiers: m,
+                    clicks,
+                };
+                handled = self.exec_handler(ElementEvent::Clicked, &click);
             }
         }
         handled
@@ -335,6 +352,9 @@ impl Element for VBox {
     fn as_any_mut(&mut self) -> &mut Any {
         self
     }
+    fn get_children(&self) -> Vec<Arc<Mutex<Element>>> {
+        self.children.clone()
+    }
 }
 
 impl HasChildren for VBox {
@@ -346,6 +366,18 @@ impl HasChildren for VBox {
     //fn get_child_mut(&mut self, i:u32) -> Option<&mut Element> {None}
     fn append(&mut self, e: Arc<Mutex<Element>>) -> Option<Arc<Mutex<Element>>> {
         self.children.push(e);
+        Manager::tree_changed();
         None
     }
+    fn insert(&mut self, i: usize, e: Arc<Mutex<Element>>) {
+        let i = i.min(self.children.len());
+        self.children.insert(i, e);
+        Manager::tree_changed();
+    }
+    fn remove(&mut self, e: &Arc<Mutex<Element>>) -> Option<usize> {
+        let i = self.children.iter().position(|c| Arc::ptr_eq(c, e))?;
+        self.children.remove(i);
+        Manager::tree_changed();
+        Some(i)
+    }
 }
@@ -0,0 +1,47 @@
+use ::clipboard::{ClipboardContext, ClipboardProvider};
+
+//implement to route copy and paste somewhere other than the system clipboard
+pub trait Clipboard: Send + Sync {
+    fn get_contents(&mut self) -> Option<String>;
+    fn set_contents(&mut self, contents: String);
+}
+
+pub struct SystemClipboard;
+
+impl Clipboard for SystemClipboard {
+    fn get_contents(&mut self) -> Option<String> {
+        //the context is not Sync on every platform, so it is not kept around
+        match ClipboardContext::new() {
+            Ok(mut ctx) => ctx.get_contents().ok(),
+            Err(_) => None,
+        }
+    }
+
+    fn set_contents(&mut self, contents: String) {
+        if let Ok(mut ctx) = ClipboardContext::new() {
+            let _ = ctx.set_contents(contents);
+        }
+    }
+}
+
+//only lives in memory, for tests
+#[derive(Default)]
+pub struct MemoryClipboard {
+    contents: Option<String>,
+}
+
+impl MemoryClipboard {
+    pub fn new() -> Self {
+        MemoryClipboard { contents: None }
+    }
+}
+
+impl Clipboard for MemoryClipboard {
+    fn get_contents(&mut self) -> Option<String> {
+        self.contents.clone()
+    }
+
+    fn set_contents(&mut self, contents: String) {
+        self.contents = Some(contents);
+    }
+}
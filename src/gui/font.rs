@@ -2,13 +2,19 @@ use app_units;
 use font_kit;
 use font_kit::{family_name::FamilyName, font, source::SystemSource};
 use super::properties::*;
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
 use webrender::api::*;
 
-use super::properties::{Align, Position};
+use super::properties::{Align, Position, WordBreak};
 use self::shaper::GlyphMetric;
 use unicode_bidi::BidiClass;
 use unicode_bidi::BidiInfo;
+use unicode_segmentation::UnicodeSegmentation;
+use xi_unicode::LineBreakIterator;
 
 mod shaper {
     use std::collections::HashMap;
@@ -20,23 +26,24 @@ mod shaper {
     use harfbuzz_sys::{
         hb_blob_create, hb_buffer_add_utf8, hb_buffer_create, hb_buffer_destroy,
         hb_buffer_get_glyph_infos, hb_buffer_get_glyph_positions, hb_buffer_set_direction,
-        hb_buffer_set_script, hb_face_create, hb_font_create,
-        hb_font_get_glyph_extents, hb_font_set_ppem, hb_font_set_scale, hb_shape,
-        //hb_blob_destroy, hb_face_destroy, hb_font_destroy,
+        hb_buffer_set_language, hb_buffer_set_script, hb_face_create, hb_font_create,
+        hb_font_get_glyph_extents, hb_font_set_ppem, hb_font_set_scale, hb_language_from_string,
+        hb_shape, hb_blob_destroy, hb_face_destroy, hb_font_destroy,
     };
     //harfbuzz structs
     use harfbuzz_sys::{
-        hb_blob_t, hb_face_t, hb_font_t, hb_glyph_extents_t,
+        hb_blob_t, hb_face_t, hb_feature_t, hb_font_t, hb_glyph_extents_t, hb_tag_t,
     };
     //harfbuzz consts
     use harfbuzz_sys::{
         HB_DIRECTION_LTR, HB_DIRECTION_RTL, HB_MEMORY_MODE_READONLY,
     };
 
-    use super::super::properties::Position;
+    use super::super::properties::{FontFeature, Position};
 
     //pub type Dimensions = ((f32, f32), (f32, f32));
-    pub type Glyph = (GlyphIndex, GlyphMetric);
+    //glyph, its metrics and the byte offset of the cluster it belongs to
+    pub type Glyph = (GlyphIndex, GlyphMetric, usize);
 
     /*#[derive(Debug, Clone)]
     pub struct Point{
@@ -55,7 +62,9 @@ mod shaper {
         pub baseline: f32,
     }
 
-    #[derive(Debug, Clone)]
+    //owns the harfbuzz objects of a family, together with the font data
+    //the blob points into. They are destroyed when the last Arc goes away.
+    #[derive(Debug)]
     struct HBFont {
         blob: usize,
         face: usize,
@@ -63,8 +72,119 @@ mod shaper {
         bytes: Vec<u8>,
     }
 
+    impl HBFont {
+        fn load(family: &str) -> HBFont {
+            let font = super::load_font_by_name(family);
+            let font_vec: Vec<u8> = (*(font.copy_font_data().unwrap())).clone();
+            let tmp_len = font_vec.len();
+            let tmp = (&font_vec).as_ptr();
+
+            unsafe {
+                let blob = hb_blob_create(
+                    tmp as *const c_char,
+                    tmp_len as c_uint,
+                    HB_MEMORY_MODE_READONLY,
+                    ptr::null_mut() as *mut c_void,
+                    None,
+                );
+
+                let face = hb_face_create(blob, 1 as c_uint);
+
+                let font = hb_font_create(face);
+
+                HBFont {
+                    blob: blob as *const hb_blob_t as usize,
+                    face: face as *const hb_face_t as usize,
+                    font: font as *const hb_font_t as usize,
+                    bytes: font_vec,
+                }
+            }
+        }
+
+        fn font(&self) -> *mut hb_font_t {
+            self.font as *mut hb_font_t
+        }
+    }
+
+    impl Drop for HBFont {
+        fn drop(&mut self) {
+            unsafe {
+                hb_font_destroy(self.font as *mut hb_font_t);
+                hb_face_destroy(self.face as *mut hb_face_t);
+                hb_blob_destroy(self.blob as *mut hb_blob_t);
+            }
+        }
+    }
+
+    //a family stays loaded while at least one FontStore holds on to it
+    struct HBEntry {
+        users: usize,
+        font: Arc<HBFont>,
+    }
+
     lazy_static! {
-        static ref FONT: Arc<Mutex<HashMap<String, HBFont>>> = Arc::new(Mutex::new(HashMap::new()));
+        static ref FONT: Arc<Mutex<HashMap<String, HBEntry>>> = Arc::new(Mutex::new(HashMap::new()));
+    }
+
+    fn get_font(family: &str) -> Arc<HBFont> {
+        let mut font_map = FONT.lock().unwrap();
+        if !font_map.contains_key(family) {
+            font_map.insert(
+                family.to_owned(),
+                HBEntry {
+                    users: 0,
+                    font: Arc::new(HBFont::load(family)),
+                },
+            );
+        }
+        font_map.get(family).unwrap().font.clone()
+    }
+
+    pub fn acquire(family: &str) {
+        let mut font_map = FONT.lock().unwrap();
+        if let Some(entry) = font_map.get_mut(family) {
+            entry.users += 1;
+            return;
+        }
+        font_map.insert(
+            family.to_owned(),
+            HBEntry {
+                users: 1,
+                font: Arc::new(HBFont::load(family)),
+            },
+        );
+    }
+
+    pub fn release(family: &str) {
+        let mut font_map = FONT.lock().unwrap();
+        let unused = match font_map.get_mut(family) {
+            Some(entry) => {
+                if entry.users > 0 {
+                    entry.users -= 1;
+                }
+                entry.users == 0
+            }
+            None => false,
+        };
+        if unused {
+            font_map.remove(family);
+        }
+    }
+
+    //drops families that were shaped with but never acquired
+    pub fn purge() {
+        let mut font_map = FONT.lock().unwrap();
+        font_map.retain(|_, entry| entry.users > 0);
+    }
+
+    //tags shorter than four letters are padded with spaces
+    fn to_hb_tag(tag: &str) -> hb_tag_t {
+        let bytes = tag.as_bytes();
+        let mut ret: hb_tag_t = 0;
+        for i in 0..4 {
+            ret = (ret << 8) | hb_tag_t::from(*bytes.get(i).unwrap_or(&b' '));
+        }
+        ret
     }
 
     pub fn shape_text(
@@ -74,43 +194,15 @@ mod shaper {
         family: &str,
         rtl: bool,
         script: super::super::script::Script,
+        features: &[FontFeature],
+        language: &str,
     ) -> Vec<Glyph> {
         //println!("\"{}\"script is {:?}", val, script);
         let script = script.to_hb_script();
+        //keeps the font alive while shaping, even if it gets released meanwhile
+        let font = get_font(family);
         unsafe {
-            let hb_font = {
-                let mut font_map = FONT.lock().unwrap();
-                if !font_map.contains_key(family) {
-                    let font = super::load_font_by_name(family);
-                    let font_vec: Vec<u8> = (*(font.copy_font_data().unwrap())).clone();
-                    let tmp_len = font_vec.len();
-                    let tmp = (&font_vec).as_ptr();
-
-                    //let tmp = (tmp).buffer();
-                    let blob = hb_blob_create(
-                        tmp as *const c_char,
-                        tmp_len as c_uint,
-                        HB_MEMORY_MODE_READONLY,
-                        ptr::null_mut() as *mut c_void,
-                        None,
-                    );
-
-                    let face = hb_face_create(blob, 1 as c_uint);
-
-                    let font = hb_font_create(face);
-
-                    let hb_font = HBFont {
-                        blob: blob as *const hb_blob_t as usize,
-                        face: face as *const hb_face_t as usize,
-                        font: font as *const hb_font_t as usize,
-                        bytes: font_vec,
-                    };
-
-                    font_map.insert(family.to_owned(), hb_font);
-                }
-
-                font_map.get(family).unwrap().clone().font as *const hb_font_t as *mut hb_font_t
-            };
+            let hb_font = font.font();
 
             hb_font_set_ppem(hb_font, size, size);
             hb_font_set_scale(hb_font, size as i32, size as i32);
@@ -129,8 +221,30 @@ mod shaper {
             } else {
                 hb_buffer_set_direction(buf, HB_DIRECTION_LTR);
             }
+            if !language.is_empty() {
+                let lang = hb_language_from_string(
+                    language.as_ptr() as *const c_char,
+                    language.len() as c_int,
+                );
+                hb_buffer_set_language(buf, lang);
+            }
 
-            hb_shape(hb_font, buf, ptr::null_mut(), 0);
+            let hb_features: Vec<hb_feature_t> = features
+                .iter()
+                .map(|f| hb_feature_t {
+                    tag: to_hb_tag(&f.tag),
+                    value: f.value,
+                    start: 0,
+                    end: std::u32::MAX,
+                })
+                .collect();
+
+            hb_shape(
+                hb_font,
+                buf,
+                hb_features.as_ptr(),
+                hb_features.len() as c_uint,
+            );
 
             let mut g_count = 0;
             let mut p_count = 0;
@@ -177,7 +291,7 @@ mod shaper {
 
                 let glyphid = (*info).codepoint;
 
-                g_vec.push((glyphid, metric));
+                g_vec.push((glyphid, metric, (*info).cluster as usize));
             }
 
             //destroy all
@@ -204,6 +318,67 @@ fn load_font_by_name(name: &str) -> font::Font {
         .unwrap()
 }
 
+//for every char, whether UAX #14 allows a break before it, never before the first
+fn break_opportunities(text: &str) -> Vec<bool> {
+    let starts: Vec<usize> = text.char_indices().map(|(b, _)| b).collect();
+    let mut ret = vec![false; starts.len()];
+
+    for (offset, _hard) in LineBreakIterator::new(text) {
+        if let Ok(j) = starts.binary_search(&offset) {
+            if j > 0 {
+                ret[j] = true;
+            }
+        }
+    }
+
+    ret
+}
+
+//start and end of every word, runs of whitespace are not words
+pub fn word_ranges(text: &[char]) -> Vec<(usize, usize)> {
+    let value: String = text.iter().collect();
+    let mut ret = vec![];
+    let mut i = 0;
+    for word in value.split_word_bounds() {
+        let len = word.chars().count();
+        if !word.trim().is_empty() {
+            ret.push((i, i + len));
+        }
+        i += len;
+    }
+    ret
+}
+
+//the word, run of whitespace or punctuation around index
+pub fn word_at(text: &[char], index: usize) -> (usize, usize) {
+    let value: String = text.iter().collect();
+    let mut i = 0;
+    let mut last = (0, 0);
+    for word in value.split_word_bounds() {
+        let len = word.chars().count();
+        if index < i + len {
+            return (i, i + len);
+        }
+        last = (i, i + len);
+        i += len;
+    }
+    last
+}
+
+//start and end of the line around index, without the line break
+pub fn line_at(text: &[char], index: usize) -> (usize, usize) {
+    let index = index.min(text.len());
+    let start = text[..index]
+        .iter()
+        .rposition(|c| *c == '\n')
+        .map_or(0, |i| i + 1);
+    let end = text[index..]
+        .iter()
+        .position(|c| *c == '\n')
+        .map_or(text.len(), |i| index + i);
+    (start, end)
+}
+
 fn add_font(font: &font_kit::font::Font, api: &RenderApi, document_id: DocumentId) -> FontKey {
     let f = font.copy_font_data().unwrap();
     let key = api.generate_font_key();
@@ -221,8 +396,11 @@ pub struct Char {
     metric: GlyphMetric,
     index: usize,
     position: Position,
+    offset: f32, //x from the left edge of its segment
+    break_before: bool, //a line may be wrapped right before this char
     rtl: bool,
     glyph: GlyphIndex,
+    has_glyph: bool, //false when merged into the glyph of a preceding char
 }
 
 impl Char {
@@ -240,8 +418,11 @@ impl Char {
             },
             index,
             position: Position { x: 0.0, y: 0.0 },
+            offset: 0.0,
+            break_before: false,
             rtl,
             glyph: 0,
+            has_glyph: true,
         }
     }
 
@@ -280,6 +461,7 @@ pub struct Segment {
     script: super::script::Script,
     chars: Vec<Char>,
     glyphs: Vec<GlyphInstance>,
+    break_before: bool,
 }
 
 impl Segment {
@@ -292,16 +474,83 @@ impl Segment {
         }
     }
 
-    pub fn breaking_class(&self) -> bool {
-        match self.class {
-            BidiClass::B => true,
-            BidiClass::WS => true,
-            BidiClass::S => true,
-            _ => false,
+    //a line may be wrapped right before this segment
+    pub fn can_break_before(&self) -> bool {
+        self.break_before
+    }
+
+    //a piece of this shaped segment, the chars keep the metrics they got
+    //when the whole segment was shaped
+    fn piece(&self, chars: Vec<Char>, break_before: bool) -> Segment {
+        let mut piece = Segment {
+            rtl: self.rtl,
+            extent: Extent::new(),
+            class: self.class,
+            script: self.script,
+            chars,
+            glyphs: vec![],
+            break_before,
+        };
+        piece.extent.h = self.extent.h;
+        piece.place_chars();
+        piece
+    }
+
+    //cuts a shaped run at its line break opportunities, so lines can be
+    //wrapped there without shaping every piece on its own
+    fn split_at_breaks(self) -> Vec<Segment> {
+        if !self.chars.iter().skip(1).any(|c| c.break_before) {
+            return vec![self];
+        }
+        let mut ret = vec![];
+        let mut chars: Vec<Char> = vec![];
+        let mut break_before = self.break_before;
+        for ch in self.chars.iter() {
+            if ch.break_before && !chars.is_empty() {
+                ret.push(self.piece(std::mem::replace(&mut chars, vec![]), break_before));
+                break_before = true;
+            }
+            chars.push(ch.clone());
         }
+        ret.push(self.piece(chars, break_before));
+        ret
     }
 
-    fn shape(&mut self, size: f32, baseline: f32, family: &str) {
+    //splits a shaped segment into pieces no wider than w, breaking between
+    //any two grapheme clusters, so combining marks stay with their base.
+    //Every piece after the first can start a new line.
+    fn split_to_width(&self, w: f32) -> Vec<Segment> {
+        let mut ret = vec![];
+        let mut chars: Vec<Char> = vec![];
+        let mut width = 0.;
+        let mut break_before = self.break_before;
+
+        let value: String = self.chars.iter().map(|c| c.char).collect();
+        let mut i = 0;
+        for cluster in value.graphemes(true) {
+            let cluster = &self.chars[i..i + cluster.chars().count()];
+            i += cluster.len();
+            let adv: f32 = cluster.iter().map(|c| c.metric.advance.x).sum();
+            if !chars.is_empty() && width + adv > w {
+                ret.push(self.piece(std::mem::replace(&mut chars, vec![]), break_before));
+                break_before = true;
+                width = 0.;
+            }
+            width += adv;
+            chars.extend_from_slice(cluster);
+        }
+        ret.push(self.piece(chars, break_before));
+        ret
+    }
+
+    fn shape(
+        &mut self,
+        size: f32,
+        baseline: f32,
+        family: &str,
+        features: &[FontFeature],
+        language: &str,
+    ) {
         let value: String = self.chars.iter().map(|c| c.char).collect();
 
         let glyphs = shaper::shape_text(
@@ -311,25 +560,65 @@ impl Segment {
             family,
             self.rtl,
             self.script,
+            features,
+            language,
         );
 
         self.glyphs.clear();
 
-        let mut _x = 0.;
-
-        let mut i = 0;
-        while i < self.chars.len() {
-            let (glyph, ref metric) = glyphs[i];
+        //one glyph per char. When a feature merges several chars into one
+        //glyph (ligatures) the first char of the cluster gets the glyph and
+        //the rest take up no space; extra glyphs add to the advance.
+        let starts: Vec<usize> = value.char_indices().map(|(b, _)| b).collect();
+        let mut per_char: Vec<Option<(GlyphIndex, GlyphMetric)>> = vec![None; self.chars.len()];
+        for (glyph, metric, cluster) in glyphs.into_iter() {
+            let j = match starts.binary_search(&cluster) {
+                Ok(j) => j,
+                Err(j) => j.saturating_sub(1),
+            };
+            if j >= per_char.len() {
+                continue;
+            }
+            match per_char[j] {
+                Some((_, ref mut m)) => m.advance.x += metric.advance.x,
+                None => per_char[j] = Some((glyph, metric)),
+            }
+        }
 
-            self.chars[i].glyph = glyph;
-            self.chars[i].metric = metric.clone();
-            self.chars[i].position.x = _x;
+        for (i, g) in per_char.into_iter().enumerate() {
+            match g {
+                Some((glyph, metric)) => {
+                    self.chars[i].glyph = glyph;
+                    self.chars[i].metric = metric;
+                    self.chars[i].has_glyph = true;
+                }
+                None => {
+                    self.chars[i].glyph = 0;
+                    self.chars[i].metric.advance = Position { x: 0.0, y: 0.0 };
+                    self.chars[i].metric.size = size;
+                    self.chars[i].metric.baseline = baseline;
+                    self.chars[i].has_glyph = false;
+                }
+            }
             self.chars[i].position.y = size;
-
-            i += 1;
-            _x += metric.advance.x;
         }
         self.extent.h = size;
+        self.place_chars();
+    }
+
+    //chars are kept in logical order, rtl ones are laid out from the right
+    fn place_chars(&mut self) {
+        let order: Vec<usize> = if self.rtl {
+            (0..self.chars.len()).rev().collect()
+        } else {
+            (0..self.chars.len()).collect()
+        };
+
+        let mut _x = 0.;
+        for i in order {
+            self.chars[i].offset = _x;
+            _x += self.chars[i].metric.advance.x;
+        }
         self.extent.w = _x;
     }
 
@@ -339,17 +628,16 @@ impl Segment {
         self.extent.x = x;
         self.extent.y = y;
 
-        let mut _x = x;
         for ch in self.chars.iter_mut() {
-            ch.position.x = _x;
+            ch.position.x = x + ch.offset;
             ch.position.y = y + ch.metric.baseline;
 
-            _x += ch.metric.advance.x;
-
-            self.glyphs.push(GlyphInstance {
-                index: ch.glyph,
-                point: LayoutPoint::new(ch.position.x, ch.position.y),
-            });
+            if ch.has_glyph {
+                self.glyphs.push(GlyphInstance {
+                    index: ch.glyph,
+                    point: LayoutPoint::new(ch.position.x, ch.position.y),
+                });
+            }
         }
     }
 }
@@ -384,9 +672,12 @@ impl ParaLine {
         &self,
         _p: &super::properties::Position,
     ) -> Option<Char> {
+        if self.segments.is_empty() || self.segments[0]._ref.chars.is_empty() {
+            return None;
+        }
         let mut ch = Some(self.segments[0]._ref.chars[0].clone());
         for r in self.segments.iter(){
-            if r._ref.class == BidiClass::R {
+            if r._ref.rtl {
                 for _c in r._ref.chars.iter().rev() {
                     if _c.position.x < _p.x {
                         ch = Some(_c.clone());
@@ -462,9 +753,11 @@ impl ParaText {
         let mut i = 0;
         for dir in line_directions.iter() {
             let _tmp = dir.1;
-            let mut prev_breaking_class = false;
             for j in i..dir.0 {
-                if line.segments[j]._ref.extent.w + tmp_line.extent.w > w && prev_breaking_class {
+                if line.segments[j]._ref.extent.w + tmp_line.extent.w > w
+                    && line.segments[j]._ref.can_break_before()
+                    && !tmp_line.segments.is_empty()
+                {
                     if para.extent.w < tmp_line.extent.w {
                         para.extent.w = tmp_line.extent.w;
                     }
@@ -477,7 +770,6 @@ impl ParaText {
                     prev_rtl_pos = 0;
                 }
 
-                prev_breaking_class = line.segments[j]._ref.breaking_class();
                 tmp_line.extent.w += line.segments[j]._ref.extent.w;
 
                 //where to insert the word?
@@ -516,10 +808,11 @@ impl ParaText {
         let mut i = 0;
         let mut ltr_pos: Option<usize> = None;
         for dir in line_directions.iter() {
-            let mut prev_breaking_class = false;
-
             for j in i..dir.0 {
-                if line.segments[j]._ref.extent.w + tmp_line.extent.w > w && prev_breaking_class {
+                if line.segments[j]._ref.extent.w + tmp_line.extent.w > w
+                    && line.segments[j]._ref.can_break_before()
+                    && !tmp_line.segments.is_empty()
+                {
                     if para.extent.w < tmp_line.extent.w {
                         para.extent.w = tmp_line.extent.w;
                     }
@@ -549,7 +842,6 @@ impl ParaText {
                     }
                 };
 
-                prev_breaking_class = line.segments[j]._ref.breaking_class();
                 tmp_line.extent.w += line.segments[j]._ref.extent.w;
 
                 tmp_line.segments.insert(tmp_pos, line.segments[j].clone());
@@ -602,33 +894,32 @@ impl ParaText {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct Paragraphs {
+//shaped text laid out at 0, 0. Once shaped it is only read, so it can be
+//shared between the cache and every Paragraphs showing it.
+#[derive(Debug)]
+struct Layout {
     extent: Extent,
     segments: Vec<Segment>,
     paras: Vec<ParaText>,
 }
 
-impl Paragraphs {
-    pub fn new() -> Paragraphs {
-        Paragraphs {
+impl Layout {
+    fn new() -> Layout {
+        Layout {
             segments: Vec::new(),
             paras: Vec::new(),
             extent: Extent::new(),
         }
     }
 
-    pub fn get_extent(&mut self) -> Extent {
-        self.extent.clone()
-    }
-
-    pub fn from_chars(text: &Vec<char>) -> Paragraphs {
+    fn from_chars(text: &[char]) -> Layout {
         let mut segments = vec![];
 
         let c_tmp = text.iter().next();
         if c_tmp.is_some() {
             let value: String = text.iter().collect();
             let info = BidiInfo::new(&value, None);
+            let breaks = break_opportunities(&value);
 
             let mut class = Segment::resolve_class(&info.levels[0], info.original_classes[0]);
             let mut script = super::script::get_script(text[0].clone());
@@ -639,6 +930,7 @@ impl Paragraphs {
                 class,
                 script,
                 glyphs: vec![],
+                break_before: false,
             };
             let mut i = 0;
             let mut j = 0;
@@ -646,19 +938,21 @@ impl Paragraphs {
             for c in text.iter() {
                 script = super::script::get_script(c.clone());
                 class = Segment::resolve_class(&info.levels[i], info.original_classes[i]);
+                let mut ch = Char::new(c.clone(), j, info.levels[i].is_rtl());
+                ch.break_before = breaks[j];
+                //a run is shaped as a whole, breaks only cut it once shaped
                 if class != BidiClass::B && class == segment.class && script == segment.script {
-                    segment
-                        .chars
-                        .push(Char::new(c.clone(), j, info.levels[i].is_rtl()));
+                    segment.chars.push(ch);
                 } else {
                     segments.push(segment);
                     segment = Segment {
-                        chars: vec![Char::new(c.clone(), j, info.levels[i].is_rtl())],
+                        chars: vec![ch],
                         rtl: info.levels[i].is_rtl(),
                         extent: Extent::new(),
                         class,
                         script,
                         glyphs: vec![],
+                        break_before: breaks[j],
                     };
                 }
 
@@ -670,19 +964,15 @@ impl Paragraphs {
             segments.push(segment);
         }
 
-        Paragraphs {
+        Layout {
             segments,
             paras: vec![],
             extent: Extent::new(),
         }
     }
 
-    fn init_paras<'a>(
-        &'a mut self,
-        size: f32,
-        baseline: f32,
-        family: &str,
-    ) -> Vec<Vec<(usize, bool)>> {
+    //the segments have to be shaped already
+    fn init_paras<'a>(&'a mut self) -> Vec<Vec<(usize, bool)>> {
         self.paras.clear();
 
         let mut ret_direction = vec![];
@@ -707,8 +997,6 @@ impl Paragraphs {
                 rtl = Some(true);
                 para.rtl = segment.rtl;
             }
-            segment.shape(size, baseline, family);
-
             let tmp = unsafe { std::mem::transmute::<&'a Segment, &'static Segment>(segment) };
             let tmp = SegmentRef { _ref: tmp };
             if direction != segment.rtl {
@@ -752,7 +1040,7 @@ impl Paragraphs {
         ret_direction
     }
 
-    pub fn shape<'a>(
+    fn shape<'a>(
         &'a mut self,
         x: f32,
         y: f32,
@@ -762,8 +1050,21 @@ impl Paragraphs {
         baseline: f32,
         family: &str,
         text_align: &Align,
+        word_break: &WordBreak,
+        features: &[FontFeature],
+        language: &str,
     ) {
-        let mut para_directions = self.init_paras(size, baseline, family);
+        let mut segments = Vec::with_capacity(self.segments.len());
+        for mut segment in self.segments.drain(..) {
+            segment.shape(size, baseline, family, features, language);
+            segments.append(&mut segment.split_at_breaks());
+        }
+        self.segments = segments;
+        if *word_break == WordBreak::Anywhere {
+            self.split_overlong(w);
+        }
+
+        let mut para_directions = self.init_paras();
 
         for para in self.paras.iter_mut() {
             let line_directions = para_directions.remove(0);
@@ -781,6 +1082,19 @@ impl Paragraphs {
         self.position(x, y, w, h, size, text_align);
     }
 
+    //breaks up shaped segments that can not fit on a line of their own
+    fn split_overlong(&mut self, w: f32) {
+        let mut segments = Vec::with_capacity(self.segments.len());
+        for segment in self.segments.drain(..) {
+            if segment.extent.w > w && segment.chars.len() > 1 {
+                segments.append(&mut segment.split_to_width(w));
+            } else {
+                segments.push(segment);
+            }
+        }
+        self.segments = segments;
+    }
+
     fn position(&mut self, x: f32, y: f32, w: f32, h: f32, size: f32, text_align: &Align) {
         let mut _y = y;
         let mut min_x = x + w;
@@ -808,7 +1122,7 @@ impl Paragraphs {
         self.extent.h = _y - y;
     }
 
-    pub fn get_char_at_pos(
+    fn get_char_at_pos(
         &self,
         _p: &super::properties::Position,
         _val: &Vec<char>,
@@ -834,7 +1148,7 @@ impl Paragraphs {
         ret
     }
 
-    pub fn get_char_at_index(&self, index: usize) -> Option<Char>{
+    fn get_char_at_index(&self, index: usize) -> Option<Char>{
         let mut ret = None;
         for para in self.paras.iter() {
             for line in para.lines.iter() {
@@ -850,7 +1164,330 @@ impl Paragraphs {
         ret
     }
 
-    pub fn glyphs(&self) -> Vec<GlyphInstance> {
+    //the line holding the char with the given logical index, and the char
+    fn find_char(&self, index: usize) -> Option<(&ParaLine, &Char)> {
+        for para in self.paras.iter() {
+            for line in para.lines.iter() {
+                for segment in line.segments.iter() {
+                    for ch in segment._ref.chars.iter() {
+                        if ch.index == index {
+                            return Some((line, ch));
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn len(&self) -> usize {
+        self.segments.iter().map(|s| s.chars.len()).sum()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    fn get_caret_at_pos(&self, p: &Position) -> usize {
+        match self.get_char_at_pos(p, &vec![]) {
+            //only the empty line after a trailing new line has no chars
+            None => match self.paras.last() {
+                Some(para) if p.y >= para.extent.y => self.len(),
+                _ => 0,
+            },
+            Some(ch) => {
+                if ch.char == '\n' {
+                    return ch.index;
+                }
+                let mid = ch.position.x + ch.metric.advance.x / 2.;
+                let after = if ch.rtl { p.x < mid } else { p.x > mid };
+                if after {
+                    ch.index + 1
+                } else {
+                    ch.index
+                }
+            }
+        }
+    }
+
+    fn get_caret_extent(&self, index: usize) -> Option<Extent> {
+        let line_start = |line: &ParaLine| Extent {
+            x: line.extent.x,
+            y: line.extent.y,
+            w: 0.0,
+            h: line.extent.h,
+            dpi: 0.0,
+        };
+
+        //the caret sits on the leading edge of the char after it...
+        if let Some((line, ch)) = self.find_char(index) {
+            let x = if ch.rtl {
+                ch.position.x + ch.metric.advance.x
+            } else {
+                ch.position.x
+            };
+            return Some(Extent {
+                x,
+                y: line.extent.y,
+                w: 0.0,
+                h: line.extent.h,
+                dpi: 0.0,
+            });
+        }
+
+        if index == 0 {
+            let para = self.paras.first()?;
+            return para.lines.first().map(line_start);
+        }
+
+        //...or on the trailing edge of the last char
+        let (line, ch) = self.find_char(index - 1)?;
+        if ch.char == '\n' {
+            return self.line_after(index - 1);
+        }
+        let x = if ch.rtl {
+            ch.position.x
+        } else {
+            ch.position.x + ch.metric.advance.x
+        };
+        Some(Extent {
+            x,
+            y: line.extent.y,
+            w: 0.0,
+            h: line.extent.h,
+            dpi: 0.0,
+        })
+    }
+
+    //where a caret after the new line at `newline` goes when no char follows
+    //it: the start of a new line right below the paragraph that ends there
+    fn line_after(&self, newline: usize) -> Option<Extent> {
+        let at = self.para_of(newline)?;
+        let para = &self.paras[at];
+        let x = match self.paras.get(at + 1).and_then(|p| p.lines.first()) {
+            Some(line) => line.extent.x,
+            None => para.lines.first()?.extent.x,
+        };
+        Some(Extent {
+            x,
+            y: para.extent.y + para.extent.h,
+            w: 0.0,
+            h: para.lines.last()?.extent.h,
+            dpi: 0.0,
+        })
+    }
+
+    fn para_of(&self, index: usize) -> Option<usize> {
+        self.paras.iter().position(|para| {
+            para.lines.iter().any(|line| {
+                line.segments
+                    .iter()
+                    .any(|s| s._ref.chars.iter().any(|ch| ch.index == index))
+            })
+        })
+    }
+
+    //the paragraph and line the caret for the given index is on
+    fn find_caret_line(&self, index: usize) -> Option<(&ParaText, &ParaLine)> {
+        let target = if self.find_char(index).is_some() {
+            index
+        } else if index > 0 {
+            index - 1
+        } else {
+            let para = self.paras.first()?;
+            return para.lines.first().map(|line| (para, line));
+        };
+
+        for para in self.paras.iter() {
+            for line in para.lines.iter() {
+                for segment in line.segments.iter() {
+                    for ch in segment._ref.chars.iter() {
+                        if ch.index == target {
+                            if ch.index < index && ch.char == '\n' {
+                                //the empty line below, if there is one
+                                let next = self.para_of(ch.index)? + 1;
+                                return match self.paras.get(next) {
+                                    Some(next) => next.lines.first().map(|line| (next, line)),
+                                    None => para.lines.last().map(|line| (para, line)),
+                                };
+                            }
+                            return Some((para, line));
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn get_caret_visual_step(&self, index: usize, right: bool) -> usize {
+        let len = self.len();
+        let (para, line) = match self.find_caret_line(index) {
+            Some(x) => x,
+            None => return index.min(len),
+        };
+        let cur_x = match self.get_caret_extent(index) {
+            Some(c) => c.x,
+            None => return index.min(len),
+        };
+
+        //both edges of every char on the line are caret stops
+        let mut target: Option<(f32, usize)> = None;
+        for segment in line.segments.iter() {
+            for ch in segment._ref.chars.iter() {
+                let (left, right_edge) = if ch.rtl {
+                    (ch.index + 1, ch.index)
+                } else {
+                    (ch.index, ch.index + 1)
+                };
+                let stops = [
+                    (ch.position.x, left),
+                    (ch.position.x + ch.metric.advance.x, right_edge),
+                ];
+                for (x, i) in stops.iter() {
+                    let beyond = if right {
+                        *x > cur_x + 0.5
+                    } else {
+                        *x < cur_x - 0.5
+                    };
+                    let closer = match target {
+                        None => true,
+                        Some((tx, _)) => {
+                            if right {
+                                *x < tx
+                            } else {
+                                *x > tx
+                            }
+                        }
+                    };
+                    if beyond && closer && *i != index {
+                        target = Some((*x, *i));
+                    }
+                }
+            }
+        }
+
+        match target {
+            Some((_, i)) => i.min(len),
+            None => {
+                if right != para.rtl {
+                    (index + 1).min(len)
+                } else {
+                    index.saturating_sub(1)
+                }
+            }
+        }
+    }
+
+    //whether the caret at index is in a right to left paragraph
+    fn is_rtl_at(&self, index: usize) -> bool {
+        self.find_caret_line(index).map_or(false, |(para, _)| para.rtl)
+    }
+
+    fn get_line_range(&self, index: usize) -> (usize, usize) {
+        let (para, line) = match self.find_caret_line(index) {
+            Some(x) => x,
+            None => return (index, index),
+        };
+
+        let mut start: Option<usize> = None;
+        let mut last: Option<&Char> = None;
+        for segment in line.segments.iter() {
+            for ch in segment._ref.chars.iter() {
+                start = Some(start.map_or(ch.index, |s| s.min(ch.index)));
+                if last.map_or(true, |l| ch.index > l.index) {
+                    last = Some(ch);
+                }
+            }
+        }
+
+        match (start, last) {
+            (Some(start), Some(ch)) => {
+                let wrapped = para
+                    .lines
+                    .last()
+                    .map_or(false, |l| !std::ptr::eq(l, line));
+                if ch.char == '\n' || (wrapped && ch.char.is_whitespace()) {
+                    (start, ch.index)
+                } else {
+                    (start, ch.index + 1)
+                }
+            }
+            _ => (index, index),
+        }
+    }
+
+    fn get_caret_vertical(&self, index: usize, x: f32, dy: f32) -> usize {
+        let c = match self.get_caret_extent(index) {
+            Some(c) => c,
+            None => return index,
+        };
+        let y = c.y + c.h / 2. + dy;
+        if y < self.extent.y {
+            0
+        } else if y > self.extent.y + self.extent.h {
+            self.len()
+        } else {
+            self.get_caret_at_pos(&Position { x, y })
+        }
+    }
+
+    fn get_selection_extents(&self, start: usize, end: usize) -> Vec<Extent> {
+        let mut rects: Vec<Extent> = vec![];
+        for para in self.paras.iter() {
+            for line in para.lines.iter() {
+                let mut line_rects = vec![];
+                for segment in line.segments.iter() {
+                    for ch in segment._ref.chars.iter() {
+                        if ch.index >= start && ch.index < end {
+                            line_rects.push(Extent {
+                                x: ch.position.x,
+                                y: line.extent.y,
+                                w: ch.metric.advance.x,
+                                h: line.extent.h,
+                                dpi: 0.0,
+                            });
+                        }
+                    }
+                }
+                line_rects.sort_by(|a, b| a.x.partial_cmp(&b.x).unwrap_or(Ordering::Equal));
+
+                let first = rects.len();
+                for r in line_rects.into_iter() {
+                    if rects.len() > first {
+                        let last = rects.last_mut().unwrap();
+                        if r.x <= last.x + last.w + 0.5 {
+                            last.w = (r.x + r.w).max(last.x + last.w) - last.x;
+                            continue;
+                        }
+                    }
+                    rects.push(r);
+                }
+            }
+        }
+        rects
+    }
+
+    fn get_para_tops(&self) -> Vec<f32> {
+        self.paras.iter().map(|para| para.extent.y).collect()
+    }
+
+    fn glyphs_in_range(&self, start: usize, end: usize) -> Vec<GlyphInstance> {
+        let mut arr = vec![];
+        for segment in self.segments.iter() {
+            for ch in segment.chars.iter() {
+                if ch.has_glyph && ch.index >= start && ch.index < end {
+                    arr.push(GlyphInstance {
+                        index: ch.glyph,
+                        point: LayoutPoint::new(ch.position.x, ch.position.y),
+                    });
+                }
+            }
+        }
+        arr
+    }
+
+    fn glyphs(&self) -> Vec<GlyphInstance> {
         let mut arr = vec![];
         for para in self.paras.iter() {
             for line in para.lines.iter() {
@@ -863,10 +1500,341 @@ impl Paragraphs {
     }
 }
 
+impl Layout {
+    fn has_text(&self, text: &[char]) -> bool {
+        self.segments
+            .iter()
+            .flat_map(|s| s.chars.iter().map(|c| c.char))
+            .eq(text.iter().cloned())
+    }
+
+    //rough size in memory, for the shape cache
+    fn bytes(&self) -> usize {
+        let mut bytes = std::mem::size_of::<Layout>();
+        for segment in self.segments.iter() {
+            bytes += std::mem::size_of::<Segment>()
+                + segment.chars.len() * std::mem::size_of::<Char>()
+                + segment.glyphs.len() * std::mem::size_of::<GlyphInstance>();
+        }
+        for para in self.paras.iter() {
+            bytes += std::mem::size_of::<ParaText>();
+            for line in para.lines.iter() {
+                bytes += std::mem::size_of::<ParaLine>()
+                    + line.segments.len() * std::mem::size_of::<SegmentRef>();
+            }
+        }
+        bytes
+    }
+}
+
+//shaped, wrapped and positioned text, the layout is shared with the cache
+#[derive(Debug, Clone)]
+pub struct Paragraphs {
+    layout: Arc<Layout>,
+    x: f32,
+    y: f32,
+}
+
+impl Paragraphs {
+    pub fn new() -> Paragraphs {
+        Paragraphs {
+            layout: Arc::new(Layout::new()),
+            x: 0.0,
+            y: 0.0,
+        }
+    }
+
+    fn moved(&self, mut e: Extent) -> Extent {
+        e.x += self.x;
+        e.y += self.y;
+        e
+    }
+
+    fn moved_char(&self, mut ch: Char) -> Char {
+        ch.position.x += self.x;
+        ch.position.y += self.y;
+        ch
+    }
+
+    fn moved_glyphs(&self, mut glyphs: Vec<GlyphInstance>) -> Vec<GlyphInstance> {
+        for g in glyphs.iter_mut() {
+            g.point.x += self.x;
+            g.point.y += self.y;
+        }
+        glyphs
+    }
+
+    //a point on screen in the coordinates of the layout
+    fn local(&self, p: &Position) -> Position {
+        Position {
+            x: p.x - self.x,
+            y: p.y - self.y,
+        }
+    }
+
+    pub fn get_extent(&mut self) -> Extent {
+        self.moved(self.layout.extent.clone())
+    }
+
+    //unshaped text, FontStore::get_paragraphs shapes it through the cache
+    pub fn from_chars(text: &Vec<char>) -> Paragraphs {
+        Paragraphs {
+            layout: Arc::new(Layout::from_chars(text)),
+            x: 0.0,
+            y: 0.0,
+        }
+    }
+
+    //shapes the text again, without the cache
+    pub fn shape(
+        &mut self,
+        x: f32,
+        y: f32,
+        w: f32,
+        h: f32,
+        size: f32,
+        baseline: f32,
+        family: &str,
+        text_align: &Align,
+        word_break: &WordBreak,
+        features: &[FontFeature],
+        language: &str,
+    ) {
+        let text: Vec<char> = self
+            .layout
+            .segments
+            .iter()
+            .flat_map(|s| s.chars.iter().map(|c| c.char))
+            .collect();
+        let mut layout = Layout::from_chars(&text);
+        layout.shape(
+            0.0, 0.0, w, h, size, baseline, family, text_align, word_break, features, language,
+        );
+        self.layout = Arc::new(layout);
+        self.x = x;
+        self.y = y;
+    }
+
+    pub fn moved_to(&self, x: f32, y: f32) -> Paragraphs {
+        Paragraphs {
+            layout: self.layout.clone(),
+            x,
+            y,
+        }
+    }
+
+    pub fn get_char_at_pos(&self, p: &Position, val: &Vec<char>) -> Option<Char> {
+        self.layout
+            .get_char_at_pos(&self.local(p), val)
+            .map(|ch| self.moved_char(ch))
+    }
+
+    pub fn get_char_at_index(&self, index: usize) -> Option<Char> {
+        self.layout
+            .get_char_at_index(index)
+            .map(|ch| self.moved_char(ch))
+    }
+
+    pub fn len(&self) -> usize {
+        self.layout.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layout.is_empty()
+    }
+
+    //the logical caret index closest to p
+    pub fn get_caret_at_pos(&self, p: &Position) -> usize {
+        self.layout.get_caret_at_pos(&self.local(p))
+    }
+
+    //x is the caret position, y and h are those of its line
+    pub fn get_caret_extent(&self, index: usize) -> Option<Extent> {
+        self.layout.get_caret_extent(index).map(|e| self.moved(e))
+    }
+
+    //one step left or right as seen on screen, backwards inside rtl runs and on
+    //to the next or previous line at the visual end of one
+    pub fn get_caret_visual_step(&self, index: usize, right: bool) -> usize {
+        self.layout.get_caret_visual_step(index, right)
+    }
+
+    pub fn is_rtl_at(&self, index: usize) -> bool {
+        self.layout.is_rtl_at(index)
+    }
+
+    //for Home and End, the end of a wrapped line is before its trailing space
+    pub fn get_line_range(&self, index: usize) -> (usize, usize) {
+        self.layout.get_line_range(index)
+    }
+
+    //the caret dy pixels above or below index, close to column x. Past the first
+    //or last line it goes to the start or end of the text
+    pub fn get_caret_vertical(&self, index: usize, x: f32, dy: f32) -> usize {
+        self.layout.get_caret_vertical(index, x - self.x, dy)
+    }
+
+    //rects covering the chars in start..end, one per run on each line
+    pub fn get_selection_extents(&self, start: usize, end: usize) -> Vec<Extent> {
+        self.layout
+            .get_selection_extents(start, end)
+            .into_iter()
+            .map(|e| self.moved(e))
+            .collect()
+    }
+
+    //top of every line of the source text
+    pub fn get_para_tops(&self) -> Vec<f32> {
+        self.layout
+            .get_para_tops()
+            .into_iter()
+            .map(|y| y + self.y)
+            .collect()
+    }
+
+    pub fn glyphs_in_range(&self, start: usize, end: usize) -> Vec<GlyphInstance> {
+        self.moved_glyphs(self.layout.glyphs_in_range(start, end))
+    }
+
+    pub fn glyphs(&self) -> Vec<GlyphInstance> {
+        self.moved_glyphs(self.layout.glyphs())
+    }
+}
+
+//everything the layout of a text depends on. Only its hash is kept.
+#[derive(Hash)]
+struct ShapeKey<'a> {
+    text: &'a [char],
+    family: &'a str,
+    size: u32,
+    baseline: u32,
+    width: u32,
+    text_align: &'a Align,
+    word_break: &'a WordBreak,
+    features: &'a [FontFeature],
+    language: &'a str,
+}
+
+impl<'a> ShapeKey<'a> {
+    fn hash_value(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+struct ShapeEntry {
+    tick: u64,
+    bytes: usize,
+    family: String,
+    layout: Arc<Layout>,
+}
+
+//Least recently used cache of shaped and wrapped text, capped by the memory
+//it takes. Layouts are shared, a hit only moves them to where they are drawn.
+struct ShapeCache {
+    capacity: usize, //in bytes
+    bytes: usize,
+    tick: u64,
+    entries: HashMap<u64, ShapeEntry>,
+    order: BTreeMap<u64, u64>, //tick of last use to key, oldest first
+}
+
+impl ShapeCache {
+    fn new(capacity: usize) -> ShapeCache {
+        ShapeCache {
+            capacity,
+            bytes: 0,
+            tick: 0,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+
+    //the text is compared as well, in case two keys have the same hash
+    fn get(&mut self, key: u64, text: &[char]) -> Option<Arc<Layout>> {
+        self.tick += 1;
+        let tick = self.tick;
+        match self.entries.get_mut(&key) {
+            Some(entry) if entry.layout.has_text(text) => {
+                self.order.remove(&entry.tick);
+                self.order.insert(tick, key);
+                entry.tick = tick;
+                Some(entry.layout.clone())
+            }
+            _ => None,
+        }
+    }
+
+    //text too big for the whole cache is not kept
+    fn insert(&mut self, key: u64, family: &str, layout: Arc<Layout>) {
+        let bytes = layout.bytes();
+        if bytes > self.capacity {
+            return;
+        }
+        self.remove(key);
+        while self.bytes + bytes > self.capacity && self.evict_oldest() {}
+        self.tick += 1;
+        self.bytes += bytes;
+        self.order.insert(self.tick, key);
+        self.entries.insert(
+            key,
+            ShapeEntry {
+                tick: self.tick,
+                bytes,
+                family: family.to_owned(),
+                layout,
+            },
+        );
+    }
+
+    fn remove(&mut self, key: u64) {
+        if let Some(entry) = self.entries.remove(&key) {
+            self.order.remove(&entry.tick);
+            self.bytes -= entry.bytes;
+        }
+    }
+
+    fn evict_oldest(&mut self) -> bool {
+        let oldest = self.order.values().next().cloned();
+        match oldest {
+            Some(k) => {
+                self.remove(k);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.bytes > self.capacity && self.evict_oldest() {}
+    }
+
+    fn remove_family(&mut self, family: &str) {
+        let keys: Vec<u64> = self
+            .entries
+            .iter()
+            .filter(|(_, v)| v.family == family)
+            .map(|(k, _)| *k)
+            .collect();
+        for key in keys {
+            self.remove(key);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+        self.bytes = 0;
+    }
+}
+
 struct InstanceKeys {
     key: FontKey,
     font: font_kit::font::Font,
-    instances: HashMap<i32, FontInstanceKey>,
+    instances: HashMap<i32, (FontInstanceKey, u64)>, //key and frame it was last used in
+    last_used: u64,
 }
 
 impl InstanceKeys {
@@ -875,18 +1843,21 @@ impl InstanceKeys {
             key,
             font,
             instances: HashMap::new(),
+            last_used: 0,
         }
     }
 
     fn get_instance_key(
         &mut self,
         size: i32,
+        frame: u64,
         api: &RenderApi,
         document_id: DocumentId,
     ) -> FontInstanceKey {
-        let x = self.instances.get(&size);
-        if x.is_some() {
-            return *x.unwrap();
+        self.last_used = frame;
+        if let Some(x) = self.instances.get_mut(&size) {
+            x.1 = frame;
+            return x.0;
         }
 
         let ikey = api.generate_font_instance_key();
@@ -902,14 +1873,46 @@ impl InstanceKeys {
         );
         api.send_transaction(document_id, txn);
 
+        self.instances.insert(size, (ikey, frame));
+
         ikey
     }
+
+    //deletes the instances not used since the given frame
+    fn evict_instances(&mut self, before: u64, txn: &mut Transaction) -> bool {
+        let old: Vec<i32> = self
+            .instances
+            .iter()
+            .filter(|(_, v)| v.1 < before)
+            .map(|(k, _)| *k)
+            .collect();
+        for size in old.iter() {
+            if let Some((ikey, _)) = self.instances.remove(size) {
+                txn.delete_font_instance(ikey);
+            }
+        }
+        !old.is_empty()
+    }
+
+    fn delete(&self, txn: &mut Transaction) {
+        for (k, _) in self.instances.values() {
+            txn.delete_font_instance(*k);
+        }
+        txn.delete_font(self.key);
+    }
 }
 
+//bytes of shaped text kept around
+const SHAPE_CACHE_CAPACITY: usize = 8 << 20;
+//fonts and font instances not used for this many frames are unloaded
+const FONT_EVICTION_FRAMES: u64 = 600;
+
 pub struct FontStore {
     store: HashMap<String, InstanceKeys>,
+    shape_cache: ShapeCache,
     api: RenderApi,
     document_id: DocumentId,
+    frame: u64,
 }
 
 impl FontStore {
@@ -918,23 +1921,82 @@ impl FontStore {
             api,
             document_id,
             store: HashMap::new(),
+            shape_cache: ShapeCache::new(SHAPE_CACHE_CAPACITY),
+            frame: 0,
         }
     }
 
+    //paragraphs shaped earlier with the same text and style are reused from the cache
+    pub fn get_paragraphs(
+        &mut self,
+        text: &Vec<char>,
+        x: f32,
+        y: f32,
+        w: f32,
+        h: f32,
+        size: f32,
+        baseline: f32,
+        family: &str,
+        text_align: &Align,
+        word_break: &WordBreak,
+        features: &[FontFeature],
+        language: &str,
+    ) -> Paragraphs {
+        let key = ShapeKey {
+            text,
+            family,
+            size: size.to_bits(),
+            baseline: baseline.to_bits(),
+            width: w.to_bits(),
+            text_align,
+            word_break,
+            features,
+            language,
+        }
+        .hash_value();
+
+        if let Some(keys) = self.store.get_mut(family) {
+            keys.last_used = self.frame;
+        }
+
+        //text is laid out at 0, 0 and moved to x, y
+        let layout = match self.shape_cache.get(key, text) {
+            Some(layout) => layout,
+            None => {
+                let mut layout = Layout::from_chars(text);
+                layout.shape(
+                    0.0, 0.0, w, h, size, baseline, family, text_align, word_break, features,
+                    language,
+                );
+                let layout = Arc::new(layout);
+                self.shape_cache.insert(key, family, layout.clone());
+                layout
+            }
+        };
+
+        Paragraphs { layout, x, y }
+    }
+
+    //bytes of shaped text kept around, 0 disables the cache
+    pub fn set_shape_cache_capacity(&mut self, capacity: usize) {
+        self.shape_cache.set_capacity(capacity);
+    }
+
     pub fn get_font_instance(&mut self, family: &str, size: i32) -> (FontKey, FontInstanceKey) {
         {
             let ikeys = self.store.get_mut(family);
             if let Some(keys) = ikeys {
-                let ik = keys.get_instance_key(size, &(self.api), self.document_id);
+                let ik = keys.get_instance_key(size, self.frame, &(self.api), self.document_id);
                 return (keys.key, ik);
             }
         }
 
         let font = load_font_by_name(family);
         let fkey = add_font(&font, &self.api, self.document_id);
+        shaper::acquire(family);
 
         let mut keys = InstanceKeys::new(fkey, font);
-        let ikey = keys.get_instance_key(size, &self.api, self.document_id);
+        let ikey = keys.get_instance_key(size, self.frame, &self.api, self.document_id);
 
         self.store.insert(family.into(), keys);
 
@@ -950,15 +2012,293 @@ impl FontStore {
         }
     }
 
-    pub fn deinit(&mut self) {
+    //unloads the families and font instances that were not used for a while
+    pub fn end_frame(&mut self) {
+        self.frame += 1;
+        if self.frame < FONT_EVICTION_FRAMES {
+            return;
+        }
+        let before = self.frame - FONT_EVICTION_FRAMES;
+
         let mut txn = Transaction::new();
-        for ik in self.store.values() {
-            for k in ik.instances.values() {
-                txn.delete_font_instance(*k);
+        let mut changed = false;
+
+        let unused: Vec<String> = self
+            .store
+            .iter()
+            .filter(|(_, v)| v.last_used < before)
+            .map(|(k, _)| k.clone())
+            .collect();
+        for family in unused.iter() {
+            if let Some(keys) = self.store.remove(family) {
+                keys.delete(&mut txn);
+                self.shape_cache.remove_family(family);
+                shaper::release(family);
+                changed = true;
             }
-            txn.delete_font(ik.key);
+        }
+
+        for keys in self.store.values_mut() {
+            changed = keys.evict_instances(before, &mut txn) || changed;
+        }
+
+        if changed {
+            self.api.send_transaction(self.document_id, txn);
+        }
+    }
+
+    pub fn deinit(&mut self) {
+        self.shape_cache.clear();
+
+        let mut txn = Transaction::new();
+        for (family, ik) in self.store.drain() {
+            ik.delete(&mut txn);
+            shaper::release(&family);
         }
         self.api.send_transaction(self.document_id, txn);
     }
 }
 
+//Unloads the families that were shaped with but that no window uses. Fonts
+//are shared by all windows, so this only runs once every window has ended
+//its frame, never from a single FontStore.
+pub(crate) fn purge_fonts() {
+    shaper::purge();
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(text: &str) -> Arc<Layout> {
+        let chars: Vec<char> = text.chars().collect();
+        Arc::new(Layout::from_chars(&chars))
+    }
+
+    fn chars(text: &str) -> Vec<char> {
+        text.chars().collect()
+    }
+
+    fn segment(text: &str, rtl: bool) -> Segment {
+        let mut chars: Vec<Char> = text
+            .chars()
+            .enumerate()
+            .map(|(i, c)| Char::new(c, i, rtl))
+            .collect();
+        for ch in chars.iter_mut() {
+            ch.metric.advance.x = 10.0;
+        }
+        Segment {
+            rtl,
+            extent: Extent::new(),
+            class: if rtl { BidiClass::R } else { BidiClass::L },
+            script: super::super::script::get_script(text.chars().next().unwrap()),
+            chars,
+            glyphs: vec![],
+            break_before: false,
+        }
+    }
+
+    #[test]
+    fn ltr_chars_are_placed_from_the_left() {
+        let mut s = segment("abc", false);
+        s.place_chars();
+        s.position(100.0, 0.0);
+        let xs: Vec<f32> = s.chars.iter().map(|c| c.position.x).collect();
+        assert_eq!(xs, vec![100.0, 110.0, 120.0]);
+        assert_eq!(s.extent.w, 30.0);
+    }
+
+    #[test]
+    fn rtl_chars_are_placed_from_the_right() {
+        let mut s = segment("אבג", true);
+        s.place_chars();
+        s.position(100.0, 0.0);
+        //logical order is kept, the first char is drawn rightmost
+        let index: Vec<usize> = s.chars.iter().map(|c| c.index).collect();
+        let xs: Vec<f32> = s.chars.iter().map(|c| c.position.x).collect();
+        assert_eq!(index, vec![0, 1, 2]);
+        assert_eq!(xs, vec![120.0, 110.0, 100.0]);
+    }
+
+    #[test]
+    fn split_pieces_are_placed_again() {
+        let mut s = segment("אבגד", true);
+        s.place_chars();
+        let pieces = s.split_to_width(20.0);
+        assert_eq!(pieces.len(), 2);
+        for piece in pieces.iter() {
+            assert_eq!(piece.extent.w, 20.0);
+            let offsets: Vec<f32> = piece.chars.iter().map(|c| c.offset).collect();
+            assert_eq!(offsets, vec![10.0, 0.0]);
+        }
+    }
+
+    fn breaks_before(text: &str) -> Vec<usize> {
+        break_opportunities(text)
+            .into_iter()
+            .enumerate()
+            .filter(|(_, b)| *b)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    #[test]
+    fn breaks_after_spaces() {
+        assert_eq!(breaks_before("one two  three"), vec![4, 9]);
+    }
+
+    #[test]
+    fn breaks_after_hyphens() {
+        assert_eq!(breaks_before("well-known"), vec![5]);
+    }
+
+    #[test]
+    fn breaks_between_cjk_ideographs() {
+        assert_eq!(breaks_before("日本語"), vec![1, 2]);
+    }
+
+    #[test]
+    fn no_break_inside_a_word() {
+        assert!(breaks_before("unbreakable").is_empty());
+        //nor before a space or a closing punctuation mark
+        assert!(breaks_before("a  b!").iter().all(|i| *i == 3));
+        assert!(break_opportunities("").is_empty());
+    }
+
+    #[test]
+    fn split_keeps_combining_marks_with_their_base() {
+        let mut s = segment("e\u{301}a\u{301}o", false);
+        s.chars[1].metric.advance.x = 0.0;
+        s.chars[3].metric.advance.x = 0.0;
+        s.place_chars();
+        let pieces = s.split_to_width(10.0);
+        let text: Vec<String> = pieces
+            .iter()
+            .map(|p| p.chars.iter().map(|c| c.char).collect())
+            .collect();
+        assert_eq!(text, vec!["e\u{301}", "a\u{301}", "o"]);
+        assert!(pieces[1].can_break_before());
+    }
+
+    #[test]
+    fn runs_are_not_cut_at_breaks_before_shaping() {
+        let layout = Layout::from_chars(&chars("日本語"));
+        assert_eq!(layout.segments.len(), 1);
+        let breaks: Vec<bool> = layout.segments[0].chars.iter().map(|c| c.break_before).collect();
+        assert_eq!(breaks, vec![false, true, true]);
+    }
+
+    #[test]
+    fn shaped_runs_are_cut_at_breaks() {
+        let mut s = segment("well-known", false);
+        s.chars[5].break_before = true;
+        s.chars[2].metric.advance.x = 7.0; //as if kerned against its neighbour
+        s.place_chars();
+        let pieces = s.split_at_breaks();
+        assert_eq!(pieces.len(), 2);
+        assert_eq!(pieces[0].extent.w, 47.0);
+        assert_eq!(pieces[1].extent.w, 50.0);
+        assert!(!pieces[0].can_break_before());
+        assert!(pieces[1].can_break_before());
+        assert_eq!(pieces[1].chars[0].offset, 0.0);
+    }
+
+    #[test]
+    fn runs_without_breaks_stay_whole() {
+        let mut s = segment("word", false);
+        s.place_chars();
+        assert_eq!(s.split_at_breaks().len(), 1);
+    }
+
+    //a cache that holds exactly `n` layouts of `text`
+    fn cache_for(n: usize, text: &str) -> ShapeCache {
+        ShapeCache::new(layout(text).bytes() * n)
+    }
+
+    #[test]
+    fn shape_cache_evicts_least_recently_used() {
+        let mut cache = cache_for(2, "aaaa");
+        cache.insert(1, "sans", layout("aaaa"));
+        cache.insert(2, "sans", layout("bbbb"));
+        cache.insert(3, "sans", layout("cccc"));
+
+        assert_eq!(cache.order.len(), cache.entries.len());
+        assert!(cache.get(1, &chars("aaaa")).is_none());
+        assert!(cache.get(2, &chars("bbbb")).is_some());
+        assert!(cache.get(3, &chars("cccc")).is_some());
+        assert_eq!(cache.bytes, layout("aaaa").bytes() * 2);
+    }
+
+    #[test]
+    fn shape_cache_get_refreshes_entry() {
+        let mut cache = cache_for(2, "aaaa");
+        cache.insert(1, "sans", layout("aaaa"));
+        cache.insert(2, "sans", layout("bbbb"));
+        assert!(cache.get(1, &chars("aaaa")).is_some());
+        cache.insert(3, "sans", layout("cccc"));
+
+        assert!(cache.get(1, &chars("aaaa")).is_some());
+        assert!(cache.get(2, &chars("bbbb")).is_none());
+        assert!(cache.get(3, &chars("cccc")).is_some());
+    }
+
+    #[test]
+    fn shape_cache_hit_shares_layout() {
+        let mut cache = cache_for(2, "aaaa");
+        let l = layout("aaaa");
+        cache.insert(1, "sans", l.clone());
+        let hit = cache.get(1, &chars("aaaa")).unwrap();
+        assert!(Arc::ptr_eq(&l, &hit));
+    }
+
+    #[test]
+    fn shape_cache_checks_text_on_hash_collision() {
+        let mut cache = cache_for(2, "aaaa");
+        cache.insert(1, "sans", layout("aaaa"));
+        assert!(cache.get(1, &chars("abcd")).is_none());
+    }
+
+    #[test]
+    fn shape_cache_shrinking_evicts() {
+        let mut cache = cache_for(3, "aaaa");
+        cache.insert(1, "sans", layout("aaaa"));
+        cache.insert(2, "sans", layout("bbbb"));
+        cache.insert(3, "sans", layout("cccc"));
+        cache.set_capacity(layout("aaaa").bytes());
+
+        assert_eq!(cache.entries.len(), 1);
+        assert!(cache.get(3, &chars("cccc")).is_some());
+    }
+
+    #[test]
+    fn shape_cache_skips_oversize_text() {
+        let mut cache = cache_for(2, "aaaa");
+        cache.insert(1, "sans", layout("aaaa"));
+        cache.insert(2, "sans", layout(&"x".repeat(100)));
+
+        assert!(cache.get(1, &chars("aaaa")).is_some());
+        assert!(cache.get(2, &chars(&"x".repeat(100))).is_none());
+    }
+
+    #[test]
+    fn shape_cache_zero_capacity_keeps_nothing() {
+        let mut cache = ShapeCache::new(0);
+        cache.insert(1, "sans", layout("aaaa"));
+        assert!(cache.get(1, &chars("aaaa")).is_none());
+        assert_eq!(cache.bytes, 0);
+    }
+
+    #[test]
+    fn shape_cache_remove_family() {
+        let mut cache = cache_for(3, "aaaa");
+        cache.insert(1, "sans", layout("aaaa"));
+        cache.insert(2, "serif", layout("bbbb"));
+        cache.remove_family("sans");
+
+        assert!(cache.get(1, &chars("aaaa")).is_none());
+        assert!(cache.get(2, &chars("bbbb")).is_some());
+        assert_eq!(cache.bytes, layout("bbbb").bytes());
+    }
+}
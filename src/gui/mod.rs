@@ -1,4 +1,6 @@
+pub mod clipboard;
 pub mod font;
 pub mod properties;
 mod script;
+mod touch;
 pub mod window;
@@ -6,6 +6,8 @@ use std::sync::{Arc, Mutex};
 use webrender::api::ColorF;
 //use webrender::api::DeviceSize
 
+use crate::gui::window::Manager;
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Point {
     pub x: f32,
@@ -58,13 +60,50 @@ impl Hash for Unit {
     }
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
 pub enum Align {
     Left,
     Middle,
     Right,
 }
 
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum WordBreak {
+    Normal,   //only at UAX #14 line break opportunities
+    Anywhere, //words wider than the line may break between any two chars
+}
+
+//The mouse pointer over an element. Default lets the elements below decide.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Cursor {
+    Default,
+    Pointer, //the hand over links and buttons
+    Text,    //the I-beam
+    ResizeHorizontal,
+    ResizeVertical,
+    ResizeDiagonal,     //top left to bottom right
+    ResizeAntiDiagonal, //top right to bottom left
+    Move,
+    NotAllowed,
+    Wait,
+}
+
+//An OpenType feature such as liga, kern, tnum, smcp or ss01
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct FontFeature {
+    pub tag: String, //four letter feature tag
+    pub value: u32,  //0 turns the feature off, 1 on, >1 picks an alternate
+}
+
+impl FontFeature {
+    pub fn new(tag: &str, value: u32) -> FontFeature {
+        FontFeature {
+            tag: tag.to_owned(),
+            value,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum Property {
     Size(i32), //in pixels
@@ -87,7 +126,19 @@ pub enum Property {
     ActiveBgColor(ColorF),
     DisabledColor(ColorF),
     DisabledBgColor(ColorF),
+    SelectionBgColor(ColorF),
+    ErrorColor(ColorF),
+    ErrorBgColor(ColorF),
+    GutterColor(ColorF),
+    GutterBgColor(ColorF),
+    CurrentLineBgColor(ColorF),
+    BracketBgColor(ColorF),
     TextAlign(Align),
+    WordBreak(WordBreak),
+    FontFeatures(Vec<FontFeature>),
+    Language(String), //BCP-47 tag, empty when not set
+    TabIndex(i32),    //focus order, 0 for tree order and negative to skip
+    Cursor(Cursor),
 }
 
 lazy_static! {
@@ -161,7 +212,54 @@ lazy_static! {
         b: 0.8,
         a: 1.0,
     });
+    pub static ref SELECTION_BG_COLOR: Property = Property::SelectionBgColor(ColorF {
+        r: 0.2,
+        g: 0.4,
+        b: 0.8,
+        a: 0.5,
+    });
+    pub static ref ERROR_COLOR: Property = Property::ErrorColor(ColorF {
+        r: 0.8,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    });
+    pub static ref ERROR_BG_COLOR: Property = Property::ErrorBgColor(ColorF {
+        r: 1.0,
+        g: 0.9,
+        b: 0.9,
+        a: 1.0,
+    });
+    pub static ref GUTTER_COLOR: Property = Property::GutterColor(ColorF {
+        r: 0.5,
+        g: 0.5,
+        b: 0.5,
+        a: 1.0,
+    });
+    pub static ref GUTTER_BG_COLOR: Property = Property::GutterBgColor(ColorF {
+        r: 0.93,
+        g: 0.93,
+        b: 0.93,
+        a: 1.0,
+    });
+    pub static ref CURRENT_LINE_BG_COLOR: Property = Property::CurrentLineBgColor(ColorF {
+        r: 0.9,
+        g: 0.9,
+        b: 1.0,
+        a: 0.5,
+    });
+    pub static ref BRACKET_BG_COLOR: Property = Property::BracketBgColor(ColorF {
+        r: 0.6,
+        g: 0.8,
+        b: 0.6,
+        a: 0.6,
+    });
     pub static ref TEXT_ALIGN: Property = Property::TextAlign(Align::Left);
+    pub static ref WORD_BREAK: Property = Property::WordBreak(WordBreak::Normal);
+    pub static ref FONT_FEATURES: Property = Property::FontFeatures(vec![]);
+    pub static ref LANGUAGE: Property = Property::Language(String::from(""));
+    pub static ref TAB_INDEX: Property = Property::TabIndex(0);
+    pub static ref CURSOR: Property = Property::Cursor(Cursor::Default);
 }
 
 impl PartialEq for Property {
@@ -211,10 +309,25 @@ impl Properties {
             .set(Property::HoverBgColor(ColorF::new(0.0, 0.0, 0.0, 0.0)))
             .set(Property::DisabledColor(ColorF::new(0.5, 0.5, 0.5, 1.0)))
             .set(Property::DisabledBgColor(ColorF::new(0.0, 0.0, 0.0, 0.0)))
+            .set(Property::SelectionBgColor(ColorF::new(0.2, 0.4, 0.8, 0.5)))
+            .set(Property::ErrorColor(ColorF::new(0.8, 0.0, 0.0, 1.0)))
+            .set(Property::ErrorBgColor(ColorF::new(1.0, 0.9, 0.9, 1.0)))
+            .set(Property::GutterColor(ColorF::new(0.5, 0.5, 0.5, 1.0)))
+            .set(Property::GutterBgColor(ColorF::new(0.93, 0.93, 0.93, 1.0)))
+            .set(Property::CurrentLineBgColor(ColorF::new(0.9, 0.9, 1.0, 0.5)))
+            .set(Property::BracketBgColor(ColorF::new(0.6, 0.8, 0.6, 0.6)))
             .set(Property::TextAlign(Align::Left))
+            .set(Property::WordBreak(WordBreak::Normal))
+            .set(Property::FontFeatures(vec![]))
+            .set(Property::Language(String::from("")))
+            .set(Property::TabIndex(0))
+            .set(Property::Cursor(Cursor::Default))
     }
 
     pub fn set(&mut self, property: Property) -> &mut Properties {
+        if let Property::TabIndex(_) = property {
+            Manager::tree_changed();
+        }
         {
             let x = &mut self.0;
             x.replace(property);
@@ -370,6 +483,62 @@ impl Properties {
         }
     }
 
+    pub fn get_selection_bg_color(&self) -> ColorF {
+        if let Some(Property::SelectionBgColor(x)) = self.get(&SELECTION_BG_COLOR) {
+            *x
+        } else {
+            panic!("Selection Background Color not found")
+        }
+    }
+
+    pub fn get_error_color(&self) -> ColorF {
+        if let Some(Property::ErrorColor(x)) = self.get(&ERROR_COLOR) {
+            *x
+        } else {
+            panic!("Error Color not found")
+        }
+    }
+
+    pub fn get_error_bg_color(&self) -> ColorF {
+        if let Some(Property::ErrorBgColor(x)) = self.get(&ERROR_BG_COLOR) {
+            *x
+        } else {
+            panic!("Error Background Color not found")
+        }
+    }
+
+    pub fn get_gutter_color(&self) -> ColorF {
+        if let Some(Property::GutterColor(x)) = self.get(&GUTTER_COLOR) {
+            *x
+        } else {
+            panic!("Gutter Color not found")
+        }
+    }
+
+    pub fn get_gutter_bg_color(&self) -> ColorF {
+        if let Some(Property::GutterBgColor(x)) = self.get(&GUTTER_BG_COLOR) {
+            *x
+        } else {
+            panic!("Gutter Background Color not found")
+        }
+    }
+
+    pub fn get_current_line_bg_color(&self) -> ColorF {
+        if let Some(Property::CurrentLineBgColor(x)) = self.get(&CURRENT_LINE_BG_COLOR) {
+            *x
+        } else {
+            panic!("Current Line Background Color not found")
+        }
+    }
+
+    pub fn get_bracket_bg_color(&self) -> ColorF {
+        if let Some(Property::BracketBgColor(x)) = self.get(&BRACKET_BG_COLOR) {
+            *x
+        } else {
+            panic!("Bracket Background Color not found")
+        }
+    }
+
     pub fn get_text_align(&self) -> Align {
         if let Some(Property::TextAlign(x)) = self.get(&TEXT_ALIGN) {
             x.clone()
@@ -377,6 +546,46 @@ impl Properties {
             panic!("Text Align not found")
         }
     }
+
+    pub fn get_word_break(&self) -> WordBreak {
+        if let Some(Property::WordBreak(x)) = self.get(&WORD_BREAK) {
+            x.clone()
+        } else {
+            panic!("Word Break not found")
+        }
+    }
+
+    pub fn get_font_features(&self) -> Vec<FontFeature> {
+        if let Some(Property::FontFeatures(x)) = self.get(&FONT_FEATURES) {
+            x.clone()
+        } else {
+            panic!("Font Features not found")
+        }
+    }
+
+    pub fn get_language(&self) -> String {
+        if let Some(Property::Language(x)) = self.get(&LANGUAGE) {
+            x.clone()
+        } else {
+            panic!("Language not found")
+        }
+    }
+
+    pub fn get_tab_index(&self) -> i32 {
+        if let Some(Property::TabIndex(x)) = self.get(&TAB_INDEX) {
+            *x
+        } else {
+            panic!("Tab Index not found")
+        }
+    }
+
+    pub fn get_cursor(&self) -> Cursor {
+        if let Some(Property::Cursor(x)) = self.get(&CURSOR) {
+            x.clone()
+        } else {
+            panic!("Cursor not found")
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -399,6 +608,14 @@ pub enum ButtonState {
     Released,
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub enum TouchPhase {
+    Started,
+    Moved,
+    Ended,
+    Cancelled,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Button {
     Left,
@@ -407,6 +624,22 @@ pub enum Button {
     Other,
 }
 
+//positive values scroll towards the top and the left, like webrender deltas
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScrollDelta {
+    Lines(f32, f32),
+    Pixels(f32, f32),
+}
+
+impl ScrollDelta {
+    pub fn to_pixels(&self, line_height: f32) -> (f32, f32) {
+        match *self {
+            ScrollDelta::Lines(x, y) => (x * line_height, y * line_height),
+            ScrollDelta::Pixels(x, y) => (x, y),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct IdGenerator {
     pub next_id: Arc<Mutex<u64>>,
@@ -0,0 +1,460 @@
+use std::time::{Duration, Instant};
+
+use crate::elements::{GesturePhase, PanEvent, PinchEvent, SwipeDirection, SwipeEvent, TapEvent};
+use crate::gui::properties::{Position, TouchPhase};
+
+//how far a finger moves before a touch is a pan rather than a tap
+const TAP_SLOP: f32 = 10.0;
+//how long a finger stays down for a long press
+const LONG_PRESS: Duration = Duration::from_millis(500);
+//a pan that ends faster than this, in pixels per second, is a swipe
+const SWIPE_VELOCITY: f32 = 600.0;
+//a finger that rests this long before lifting has stopped moving
+const REST_TIME: Duration = Duration::from_millis(100);
+//the part of the speed of a fling left after a second
+const FLING_DECAY: f32 = 0.05;
+//pixels per second below which a fling stops
+const FLING_STOP: f32 = 20.0;
+
+pub enum Gesture {
+    Tap(TapEvent),
+    LongPress(TapEvent),
+    Pan(PanEvent),
+    Pinch(PinchEvent),
+    Swipe(SwipeEvent),
+}
+
+struct Point {
+    id: u64,
+    start: Position,
+    position: Position,
+    moved_at: Instant,
+    velocity: (f32, f32),
+}
+
+enum State {
+    Idle,
+    Pending(Instant), //one finger down since, may still tap or long press
+    Panning,
+    Pinching(f32),    //distance between the fingers at the start
+    Done,             //waiting for the fingers to lift
+}
+
+//turns the touch points of a window into gestures, one at a time
+pub struct Recognizer {
+    points: Vec<Point>,
+    state: State,
+}
+
+fn distance(a: &Position, b: &Position) -> f32 {
+    let (dx, dy) = (a.x - b.x, a.y - b.y);
+    (dx * dx + dy * dy).sqrt()
+}
+
+fn center(a: &Position, b: &Position) -> Position {
+    Position {
+        x: (a.x + b.x) / 2.0,
+        y: (a.y + b.y) / 2.0,
+    }
+}
+
+impl Recognizer {
+    pub fn new() -> Recognizer {
+        Recognizer {
+            points: vec![],
+            state: State::Idle,
+        }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    pub fn touch(&mut self, p: &Position, phase: &TouchPhase, id: u64, now: Instant) -> Vec<Gesture> {
+        match phase {
+            TouchPhase::Started => self.start(p, id, now),
+            TouchPhase::Moved => self.moved(p, id, now),
+            TouchPhase::Ended => self.end(id, true, now),
+            TouchPhase::Cancelled => self.end(id, false, now),
+        }
+    }
+
+    //a finger held still long enough is a long press, even without events
+    pub fn update(&mut self, now: Instant) -> Vec<Gesture> {
+        match self.state {
+            State::Pending(since) if now.duration_since(since) >= LONG_PRESS => {
+                self.state = State::Done;
+                vec![Gesture::LongPress(TapEvent {
+                    position: self.points[0].start.clone(),
+                })]
+            }
+            _ => vec![],
+        }
+    }
+
+    fn start(&mut self, p: &Position, id: u64, now: Instant) -> Vec<Gesture> {
+        self.points.push(Point {
+            id,
+            start: p.clone(),
+            position: p.clone(),
+            moved_at: now,
+            velocity: (0.0, 0.0),
+        });
+        let mut gestures = vec![];
+        match self.state {
+            State::Idle => self.state = State::Pending(now),
+            //a second finger turns a tap or a pan into a pinch
+            State::Pending(_) | State::Panning if self.points.len() == 2 => {
+                if let State::Panning = self.state {
+                    gestures.push(self.pan(GesturePhase::Ended, (0.0, 0.0), (0.0, 0.0)));
+                }
+                let (a, b) = (&self.points[0].position, &self.points[1].position);
+                self.state = State::Pinching(distance(a, b).max(1.0));
+                gestures.push(Gesture::Pinch(PinchEvent {
+                    center: center(a, b),
+                    scale: 1.0,
+                    phase: GesturePhase::Began,
+                }));
+            }
+            _ => (),
+        }
+        gestures
+    }
+
+    fn moved(&mut self, p: &Position, id: u64, now: Instant) -> Vec<Gesture> {
+        let index = match self.points.iter().position(|t| t.id == id) {
+            Some(index) => index,
+            None => return vec![],
+        };
+        let delta = {
+            let point = &mut self.points[index];
+            let delta = (p.x - point.position.x, p.y - point.position.y);
+            let dt = now.duration_since(point.moved_at).as_millis() as f32 / 1000.0;
+            if dt > 0.0 {
+                //smoothed, single events are noisy
+                point.velocity = (
+                    point.velocity.0 * 0.2 + delta.0 / dt * 0.8,
+                    point.velocity.1 * 0.2 + delta.1 / dt * 0.8,
+                );
+            }
+            point.position = p.clone();
+            point.moved_at = now;
+            delta
+        };
+
+        match self.state {
+            State::Pending(_) => {
+                let start = &self.points[index].start;
+                if distance(start, p) > TAP_SLOP {
+                    self.state = State::Panning;
+                    //the slop is part of the first move
+                    let delta = (p.x - start.x, p.y - start.y);
+                    let velocity = self.points[index].velocity;
+                    return vec![self.pan(GesturePhase::Began, delta, velocity)];
+                }
+                vec![]
+            }
+            State::Panning => {
+                let velocity = self.points[index].velocity;
+                vec![self.pan(GesturePhase::Changed, delta, velocity)]
+            }
+            State::Pinching(start) if index < 2 => {
+                let (a, b) = (&self.points[0].position, &self.points[1].position);
+                vec![Gesture::Pinch(PinchEvent {
+                    center: center(a, b),
+                    scale: distance(a, b) / start,
+                    phase: GesturePhase::Changed,
+                })]
+            }
+            _ => vec![],
+        }
+    }
+
+    fn end(&mut self, id: u64, lifted: bool, now: Instant) -> Vec<Gesture> {
+        let index = match self.points.iter().position(|t| t.id == id) {
+            Some(index) => index,
+            None => return vec![],
+        };
+        let mut gestures = vec![];
+        match self.state {
+            State::Pending(_) if lifted => gestures.push(Gesture::Tap(TapEvent {
+                position: self.points[index].position.clone(),
+            })),
+            State::Panning => {
+                let point = &self.points[index];
+                let velocity = if lifted && now.duration_since(point.moved_at) < REST_TIME {
+                    point.velocity
+                } else {
+                    (0.0, 0.0)
+                };
+                gestures.push(self.pan(GesturePhase::Ended, (0.0, 0.0), velocity));
+                let speed = (velocity.0 * velocity.0 + velocity.1 * velocity.1).sqrt();
+                if speed >= SWIPE_VELOCITY {
+                    let direction = if velocity.0.abs() > velocity.1.abs() {
+                        if velocity.0 > 0.0 {
+                            SwipeDirection::Right
+                        } else {
+                            SwipeDirection::Left
+                        }
+                    } else if velocity.1 > 0.0 {
+                        SwipeDirection::Down
+                    } else {
+                        SwipeDirection::Up
+                    };
+                    gestures.push(Gesture::Swipe(SwipeEvent {
+                        position: point.position.clone(),
+                        direction,
+                        velocity: speed,
+                    }));
+                }
+            }
+            State::Pinching(start) if index < 2 => {
+                let (a, b) = (&self.points[0].position, &self.points[1].position);
+                gestures.push(Gesture::Pinch(PinchEvent {
+                    center: center(a, b),
+                    scale: distance(a, b) / start,
+                    phase: GesturePhase::Ended,
+                }));
+            }
+            _ => (),
+        }
+        self.points.remove(index);
+        self.state = match self.state {
+            _ if self.points.is_empty() => State::Idle,
+            State::Pinching(start) if index >= 2 => State::Pinching(start),
+            _ => State::Done,
+        };
+        gestures
+    }
+
+    fn pan(&self, phase: GesturePhase, delta: (f32, f32), velocity: (f32, f32)) -> Gesture {
+        Gesture::Pan(PanEvent {
+            position: self.points[0].position.clone(),
+            delta,
+            velocity,
+            phase,
+        })
+    }
+}
+
+impl Default for Recognizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//scrolling that goes on after a pan until friction stops it
+pub struct Fling {
+    pub at: Position,
+    velocity: (f32, f32),
+    last: Instant,
+}
+
+impl Fling {
+    pub fn new(at: Position, velocity: (f32, f32), now: Instant) -> Fling {
+        Fling {
+            at,
+            velocity,
+            last: now,
+        }
+    }
+
+    //how far to scroll since the last step, slowing down on the way
+    pub fn step(&mut self, now: Instant) -> (f32, f32) {
+        let dt = now.duration_since(self.last).as_millis() as f32 / 1000.0;
+        self.last = now;
+        let delta = (self.velocity.0 * dt, self.velocity.1 * dt);
+        let decay = FLING_DECAY.powf(dt);
+        self.velocity = (self.velocity.0 * decay, self.velocity.1 * decay);
+        delta
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        let (vx, vy) = self.velocity;
+        (vx * vx + vy * vy).sqrt() < FLING_STOP
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(x: f32, y: f32) -> Position {
+        Position { x, y }
+    }
+
+    fn ms(t: Instant, ms: u64) -> Instant {
+        t + Duration::from_millis(ms)
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 0.01
+    }
+
+    #[test]
+    fn tap() {
+        let t = Instant::now();
+        let mut r = Recognizer::new();
+        assert!(r.touch(&at(10.0, 10.0), &TouchPhase::Started, 1, t).is_empty());
+        //moving within the slop is still a tap
+        assert!(r.touch(&at(15.0, 12.0), &TouchPhase::Moved, 1, ms(t, 30)).is_empty());
+        let g = r.touch(&at(15.0, 12.0), &TouchPhase::Ended, 1, ms(t, 60));
+        match g.as_slice() {
+            [Gesture::Tap(tap)] => assert_eq!(tap.position, at(15.0, 12.0)),
+            _ => panic!("expected a tap"),
+        }
+        assert!(r.is_idle());
+    }
+
+    #[test]
+    fn cancelled_touch_does_not_tap() {
+        let t = Instant::now();
+        let mut r = Recognizer::new();
+        r.touch(&at(10.0, 10.0), &TouchPhase::Started, 1, t);
+        assert!(r.touch(&at(10.0, 10.0), &TouchPhase::Cancelled, 1, ms(t, 60)).is_empty());
+        assert!(r.is_idle());
+    }
+
+    #[test]
+    fn long_press() {
+        let t = Instant::now();
+        let mut r = Recognizer::new();
+        r.touch(&at(10.0, 10.0), &TouchPhase::Started, 1, t);
+        assert!(r.update(ms(t, 400)).is_empty());
+        match r.update(ms(t, 500)).as_slice() {
+            [Gesture::LongPress(press)] => assert_eq!(press.position, at(10.0, 10.0)),
+            _ => panic!("expected a long press"),
+        }
+        //only once, and no tap when the finger lifts
+        assert!(r.update(ms(t, 900)).is_empty());
+        assert!(r.touch(&at(10.0, 10.0), &TouchPhase::Ended, 1, ms(t, 1000)).is_empty());
+        assert!(r.is_idle());
+    }
+
+    #[test]
+    fn pan() {
+        let t = Instant::now();
+        let mut r = Recognizer::new();
+        r.touch(&at(0.0, 0.0), &TouchPhase::Started, 1, t);
+        match r.touch(&at(20.0, 0.0), &TouchPhase::Moved, 1, ms(t, 16)).as_slice() {
+            [Gesture::Pan(pan)] => {
+                assert_eq!(pan.phase, GesturePhase::Began);
+                assert_eq!(pan.delta, (20.0, 0.0));
+            }
+            _ => panic!("expected a pan to begin"),
+        }
+        match r.touch(&at(30.0, 5.0), &TouchPhase::Moved, 1, ms(t, 32)).as_slice() {
+            [Gesture::Pan(pan)] => {
+                assert_eq!(pan.phase, GesturePhase::Changed);
+                assert_eq!(pan.delta, (10.0, 5.0));
+                assert_eq!(pan.position, at(30.0, 5.0));
+            }
+            _ => panic!("expected a pan"),
+        }
+        //the finger rested before lifting, so no velocity and no swipe
+        match r.touch(&at(30.0, 5.0), &TouchPhase::Ended, 1, ms(t, 300)).as_slice() {
+            [Gesture::Pan(pan)] => {
+                assert_eq!(pan.phase, GesturePhase::Ended);
+                assert_eq!(pan.velocity, (0.0, 0.0));
+            }
+            _ => panic!("expected a pan to end"),
+        }
+        //a long press never comes once panning
+        assert!(r.update(ms(t, 1000)).is_empty());
+    }
+
+    #[test]
+    fn swipe() {
+        let t = Instant::now();
+        let mut r = Recognizer::new();
+        r.touch(&at(0.0, 0.0), &TouchPhase::Started, 1, t);
+        r.touch(&at(0.0, -20.0), &TouchPhase::Moved, 1, ms(t, 10));
+        r.touch(&at(0.0, -40.0), &TouchPhase::Moved, 1, ms(t, 20));
+        r.touch(&at(0.0, -60.0), &TouchPhase::Moved, 1, ms(t, 30));
+        let g = r.touch(&at(0.0, -60.0), &TouchPhase::Ended, 1, ms(t, 40));
+        match g.as_slice() {
+            [Gesture::Pan(pan), Gesture::Swipe(swipe)] => {
+                assert_eq!(pan.phase, GesturePhase::Ended);
+                assert!(pan.velocity.1 < -SWIPE_VELOCITY);
+                assert_eq!(swipe.direction, SwipeDirection::Up);
+                assert!(swipe.velocity >= SWIPE_VELOCITY);
+            }
+            _ => panic!("expected a pan to end with a swipe"),
+        }
+    }
+
+    #[test]
+    fn pinch() {
+        let t = Instant::now();
+        let mut r = Recognizer::new();
+        r.touch(&at(0.0, 0.0), &TouchPhase::Started, 1, t);
+        match r.touch(&at(100.0, 0.0), &TouchPhase::Started, 2, ms(t, 10)).as_slice() {
+            [Gesture::Pinch(pinch)] => {
+                assert_eq!(pinch.phase, GesturePhase::Began);
+                assert_eq!(pinch.center, at(50.0, 0.0));
+                assert!(close(pinch.scale, 1.0));
+            }
+            _ => panic!("expected a pinch to begin"),
+        }
+        match r.touch(&at(200.0, 0.0), &TouchPhase::Moved, 2, ms(t, 30)).as_slice() {
+            [Gesture::Pinch(pinch)] => {
+                assert_eq!(pinch.phase, GesturePhase::Changed);
+                assert_eq!(pinch.center, at(100.0, 0.0));
+                assert!(close(pinch.scale, 2.0));
+            }
+            _ => panic!("expected a pinch"),
+        }
+        match r.touch(&at(200.0, 0.0), &TouchPhase::Ended, 2, ms(t, 50)).as_slice() {
+            [Gesture::Pinch(pinch)] => {
+                assert_eq!(pinch.phase, GesturePhase::Ended);
+                assert!(close(pinch.scale, 2.0));
+            }
+            _ => panic!("expected a pinch to end"),
+        }
+        //the finger left behind does nothing until it lifts
+        assert!(r.touch(&at(30.0, 0.0), &TouchPhase::Moved, 1, ms(t, 60)).is_empty());
+        assert!(r.touch(&at(30.0, 0.0), &TouchPhase::Ended, 1, ms(t, 70)).is_empty());
+        assert!(r.is_idle());
+    }
+
+    #[test]
+    fn second_finger_cancels_tap() {
+        let t = Instant::now();
+        let mut r = Recognizer::new();
+        r.touch(&at(0.0, 0.0), &TouchPhase::Started, 1, t);
+        r.touch(&at(50.0, 0.0), &TouchPhase::Started, 2, ms(t, 20));
+        let mut g = r.touch(&at(50.0, 0.0), &TouchPhase::Ended, 2, ms(t, 40));
+        g.append(&mut r.touch(&at(0.0, 0.0), &TouchPhase::Ended, 1, ms(t, 50)));
+        assert!(g.iter().all(|g| match g {
+            Gesture::Tap(_) | Gesture::LongPress(_) => false,
+            _ => true,
+        }));
+        assert!(r.update(ms(t, 1000)).is_empty());
+        assert!(r.is_idle());
+    }
+
+    #[test]
+    fn fling_decays() {
+        let t = Instant::now();
+        let mut fling = Fling::new(at(0.0, 0.0), (1000.0, -500.0), t);
+        let (dx, dy) = fling.step(ms(t, 1000));
+        assert!(close(dx, 1000.0) && close(dy, -500.0));
+        //after a second FLING_DECAY of the speed is left
+        assert!(close(fling.velocity.0, 1000.0 * FLING_DECAY));
+        assert!(close(fling.velocity.1, -500.0 * FLING_DECAY));
+        assert!(!fling.is_stopped());
+
+        let (dx, _) = fling.step(ms(t, 1500));
+        assert!(close(dx, 1000.0 * FLING_DECAY * 0.5));
+        fling.step(ms(t, 3000));
+        assert!(fling.is_stopped());
+    }
+
+    #[test]
+    fn fling_without_time_does_not_move() {
+        let t = Instant::now();
+        let mut fling = Fling::new(at(0.0, 0.0), (FLING_STOP * 2.0, 0.0), t);
+        assert_eq!(fling.step(t), (0.0, 0.0));
+        assert!(!fling.is_stopped());
+    }
+}
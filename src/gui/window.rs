@@ -2,19 +2,27 @@ use euclid;
 use gleam::gl;
 use glutin;
 use glutin::ContextWrapper;
+use glutin::VirtualKeyCode;
 use webrender;
 use webrender::api::*;
 
-use crate::elements::{Element, PrimitiveEvent};
+use crate::elements::{
+    DragData, DragEndEvent, DragEvent, DragStartEvent, Element, ElementEvent, Event, EventPhase,
+    GesturePhase, Menu, PrimitiveEvent,
+};
 use crate::gui::font;
 use crate::gui::properties;
+use crate::gui::touch::{Fling, Gesture, Recognizer};
 use crate::util::*;
 
+use std::any::Any;
+use std::cell::RefCell;
 use std::mem;
 use std::ops::DerefMut;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 use std::fmt;
 
 impl Into<properties::Position> for glutin::dpi::LogicalPosition {
@@ -74,6 +82,17 @@ impl Into<properties::ButtonState> for glutin::ElementState {
     }
 }
 
+impl Into<properties::TouchPhase> for glutin::TouchPhase {
+    fn into(self) -> properties::TouchPhase {
+        match self {
+            glutin::TouchPhase::Started => properties::TouchPhase::Started,
+            glutin::TouchPhase::Moved => properties::TouchPhase::Moved,
+            glutin::TouchPhase::Ended => properties::TouchPhase::Ended,
+            glutin::TouchPhase::Cancelled => properties::TouchPhase::Cancelled,
+        }
+    }
+}
+
 struct WindowNotifier {
     events_proxy: glutin::EventsLoopProxy,
 }
@@ -120,6 +139,10 @@ struct Internals {
     cursor_position: WorldPoint,
     dpi: f64,
     cursor_in_window: bool,
+    last_click: Option<(SystemTime, properties::Position, properties::Button)>,
+    click_count: u32,
+    cursor: properties::Cursor, //the one last given to the OS
+    focused: bool,              //whether the OS gives this window the keyboard
 }
 
 impl fmt::Debug for Internals {
@@ -207,6 +230,10 @@ impl Internals {
             cursor_position: WorldPoint::new(0.0, 0.0),
             dpi,
             cursor_in_window: false,
+            last_click: None,
+            click_count: 0,
+            cursor: properties::Cursor::Default,
+            focused: false,
         }
     }
 
@@ -216,7 +243,10 @@ impl Internals {
         let mut cursor_in_window = self.cursor_in_window;
         let mut cursor_position = self.cursor_position;
         let mut dpi = self.dpi;
-        let mut txn = None;
+        let mut last_click = self.last_click.clone();
+        let mut click_count = self.click_count;
+        let mut focused = self.focused;
+        let (click_time, click_distance) = *CLICK_SETTINGS.lock().unwrap();
 
         self.events_loop.poll_events(|event| {
             match event {
@@ -269,6 +299,12 @@ impl Internals {
                     cursor_position.x = -1.0;
                     cursor_position.y = -1.0;
                 }
+                glutin::Event::WindowEvent {
+                    event: glutin::WindowEvent::Focused(f),
+                    ..
+                } => {
+                    focused = f;
+                }
                 glutin::Event::WindowEvent {
                     event: glutin::WindowEvent::Resized(size),
                     ..
@@ -293,7 +329,7 @@ impl Internals {
                     ..
                 } => {
                     let _pos: properties::Position = cursor_position.into();
-                    let _button = button.into();
+                    let _button: properties::Button = button.into();
                     let _state = state.into();
                     let _modifiers = modifiers.into();
 
@@ -303,7 +339,28 @@ impl Internals {
                     {
                         events.push(PrimitiveEvent::SetFocus(true));
                     }
-                    events.push(PrimitiveEvent::Button(_pos, _button, _state, _modifiers));
+                    //presses close in time and place to the last one count up
+                    if state == glutin::ElementState::Pressed {
+                        let now = SystemTime::now();
+                        let repeat = match last_click {
+                            Some((t, ref p, ref b)) => {
+                                *b == _button
+                                    && now.duration_since(t).map_or(false, |d| d <= click_time)
+                                    && (p.x - _pos.x).abs() <= click_distance
+                                    && (p.y - _pos.y).abs() <= click_distance
+                            }
+                            None => false,
+                        };
+                        click_count = if repeat { click_count + 1 } else { 1 };
+                        last_click = Some((now, _pos.clone(), _button.clone()));
+                    }
+                    events.push(PrimitiveEvent::Button(
+                        _pos,
+                        _button,
+                        _state,
+                        _modifiers,
+                        click_count,
+                    ));
                 }
                 glutin::Event::WindowEvent {
                     event:
@@ -312,30 +369,19 @@ impl Internals {
                         },
                     ..
                 } => {
-                    if txn.is_none() {
-                        txn = Some(Transaction::new());
-                    }
-                    const LINE_HEIGHT: f32 = 38.0;
-                    let (dx, dy) = if modifiers.alt {
-                        match delta {
-                            glutin::MouseScrollDelta::LineDelta(_, dy) => (dy * LINE_HEIGHT, 0.0),
-                            glutin::MouseScrollDelta::PixelDelta(pos) => (pos.y as f32, 0.0),
+                    let delta = match delta {
+                        glutin::MouseScrollDelta::LineDelta(dx, dy) => {
+                            properties::ScrollDelta::Lines(dx, dy)
                         }
-                    } else {
-                        match delta {
-                            glutin::MouseScrollDelta::LineDelta(_, dy) => (0.0, dy * LINE_HEIGHT),
-                            glutin::MouseScrollDelta::PixelDelta(pos) => (0.0, pos.y as f32),
+                        glutin::MouseScrollDelta::PixelDelta(pos) => {
+                            properties::ScrollDelta::Pixels(pos.x as f32, pos.y as f32)
                         }
                     };
-
-                    if let Some(ref mut _txn) = txn {
-                        _txn.scroll(
-                            ScrollLocation::Delta(LayoutVector2D::new(dx, dy)),
-                            cursor_position,
-                        );
-                    }
-
-                    //println!("scrolling {} {}",dx,dy);
+                    events.push(PrimitiveEvent::Wheel(
+                        cursor_position.into(),
+                        delta,
+                        modifiers.into(),
+                    ));
                 }
                 glutin::Event::WindowEvent {
                     event:
@@ -358,6 +404,33 @@ impl Internals {
                         modifiers.into(),
                     ));
                 }
+                glutin::Event::WindowEvent {
+                    event: glutin::WindowEvent::HoveredFile(path),
+                    ..
+                } => {
+                    events.push(PrimitiveEvent::FileHovered(cursor_position.into(), path));
+                }
+                glutin::Event::WindowEvent {
+                    event: glutin::WindowEvent::DroppedFile(path),
+                    ..
+                } => {
+                    events.push(PrimitiveEvent::FileDropped(cursor_position.into(), path));
+                }
+                glutin::Event::WindowEvent {
+                    event: glutin::WindowEvent::HoveredFileCancelled,
+                    ..
+                } => {
+                    events.push(PrimitiveEvent::FileHoverCancelled);
+                }
+                glutin::Event::WindowEvent {
+                    event:
+                        glutin::WindowEvent::Touch(glutin::Touch {
+                            phase, location, id, ..
+                        }),
+                    ..
+                } => {
+                    events.push(PrimitiveEvent::Touch(location.into(), phase.into(), id));
+                }
                 glutin::Event::WindowEvent {
                     event: glutin::WindowEvent::ReceivedCharacter(c),
                     ..
@@ -374,17 +447,52 @@ impl Internals {
 
         self.dpi = dpi;
 
-        if let Some(mut _txn) = txn {
-            _txn.generate_frame();
-            self.api.send_transaction(self.document_id, _txn);
-        }
-
         self.cursor_in_window = cursor_in_window;
         self.cursor_position = cursor_position;
+        self.last_click = last_click;
+        self.click_count = click_count;
+        self.focused = focused;
 
         events
     }
 
+    //scrolls the scroll frame under `at`
+    fn scroll(&self, dx: f32, dy: f32, at: WorldPoint) {
+        let mut txn = Transaction::new();
+        txn.scroll(ScrollLocation::Delta(LayoutVector2D::new(dx, dy)), at);
+        txn.generate_frame();
+        self.api.send_transaction(self.document_id, txn);
+    }
+
+    //the tags at p, bottom first like the cursor tags, so touches and the
+    //mouse find the same event path
+    fn hit_test(&self, p: WorldPoint) -> Vec<ItemTag> {
+        let results = self.api.hit_test(self.document_id, None, p, HitTestFlags::FIND_ALL);
+        results.items.iter().rev().map(|item| item.tag).collect()
+    }
+
+    fn set_cursor(&mut self, cursor: properties::Cursor) {
+        if cursor == self.cursor {
+            return;
+        }
+        let icon = match cursor {
+            properties::Cursor::Default => glutin::MouseCursor::Default,
+            properties::Cursor::Pointer => glutin::MouseCursor::Hand,
+            properties::Cursor::Text => glutin::MouseCursor::Text,
+            properties::Cursor::ResizeHorizontal => glutin::MouseCursor::EwResize,
+            properties::Cursor::ResizeVertical => glutin::MouseCursor::NsResize,
+            properties::Cursor::ResizeDiagonal => glutin::MouseCursor::NwseResize,
+            properties::Cursor::ResizeAntiDiagonal => glutin::MouseCursor::NeswResize,
+            properties::Cursor::Move => glutin::MouseCursor::Move,
+            properties::Cursor::NotAllowed => glutin::MouseCursor::NotAllowed,
+            properties::Cursor::Wait => glutin::MouseCursor::Wait,
+        };
+        if let Some(ref window) = self.gl_window {
+            window.window().set_cursor(icon);
+        }
+        self.cursor = cursor;
+    }
+
     fn get_window_id(&self) -> Option<glutin::WindowId> {
         match self.gl_window {
             None => None,
@@ -393,12 +501,116 @@ impl Internals {
 
     }
 
+    //fonts have to be released before the api shuts down
     fn deinit(self) {
         self.font_store.lock().unwrap().deinit();
+        self.api.shut_down();
         self.renderer.deinit();
     }
 }
 
+//an element that can take focus, with the tags leading to it from the root
+#[derive(Clone)]
+struct Focusable {
+    tab_index: i32,
+    path: Vec<ItemTag>,
+    element: Arc<Mutex<Element>>,
+}
+
+//focusable elements under elm in tree order
+fn collect_focusable(elm: &Arc<Mutex<Element>>, path: &mut Vec<ItemTag>, out: &mut Vec<Focusable>) {
+    let e = elm.lock().unwrap();
+    path.push((e.get_ext_id(), 0));
+    let tab_index = e.get_properties().get_tab_index();
+    let enabled = e.as_can_disable().map_or(true, |c| c.get_enabled());
+    if e.is_focusable() && enabled && tab_index >= 0 {
+        out.push(Focusable {
+            tab_index,
+            path: path.clone(),
+            element: elm.clone(),
+        });
+    }
+    for child in e.get_children().iter() {
+        collect_focusable(child, path, out);
+    }
+    path.pop();
+}
+
+//finds the element with ext id `id` below elm, pushing the elements leading to it
+fn find_below(elm: &Arc<Mutex<Element>>, id: u64, out: &mut Vec<Arc<Mutex<Element>>>) -> bool {
+    let children = elm.lock().unwrap().get_children();
+    for child in children.iter() {
+        out.push(child.clone());
+        if child.lock().unwrap().get_ext_id() == id || find_below(child, id, out) {
+            return true;
+        }
+        out.pop();
+    }
+    false
+}
+
+//walks the tree below elm once, keeping in best the path to the element with
+//the deepest of the tags. A tag only counts below the elements of earlier
+//ones, and an element with several tags gets the deepest one.
+fn find_path(
+    elm: &Arc<Mutex<Element>>,
+    tags: &[ItemTag],
+    last: Option<usize>,
+    path: &mut Vec<(Arc<Mutex<Element>>, Option<usize>)>,
+    best: &mut Vec<(Arc<Mutex<Element>>, Option<usize>)>,
+) {
+    let (id, children) = {
+        let e = elm.lock().unwrap();
+        (e.get_ext_id(), e.get_children())
+    };
+    let index = tags
+        .iter()
+        .enumerate()
+        .rev()
+        .find(|(i, t)| t.0 == id && last.map_or(true, |l| *i > l))
+        .map(|(i, _)| i);
+    path.push((elm.clone(), index));
+    if index.is_some() && index > best.last().and_then(|b| b.1) {
+        *best = path.clone();
+    }
+    let last = index.or(last);
+    //nothing deeper to find once the last tag is matched
+    if last.map_or(true, |l| l + 1 < tags.len()) {
+        for child in children.iter() {
+            find_path(child, tags, last, path, best);
+        }
+    }
+    path.pop();
+}
+
+//how far the cursor moves with the button held before a drag starts
+const DRAG_THRESHOLD: f32 = 4.0;
+
+//a left button press that may turn into a drag
+struct Press {
+    position: properties::Position,
+    tags: Vec<ItemTag>,
+    modifiers: properties::Modifiers,
+}
+
+//a drag in progress, files dragged in from the desktop have no source
+struct Drag {
+    data: DragData,
+    source: Option<Arc<Mutex<Element>>>,
+    offset: properties::Position, //of the grab point within the drag image
+    size: (f32, f32),
+    position: properties::Position,
+    over: Vec<Arc<Mutex<Element>>>, //elements under the cursor, root first
+    accepted: bool,
+}
+
+enum FocusRequest {
+    Element(Arc<Mutex<Element>>),
+    Next,
+    Previous,
+    Clear,
+}
+
 pub struct Window {
     width: f64,
     height: f64,
@@ -407,8 +619,30 @@ pub struct Window {
     id_generator: properties::IdGenerator,
     internals: Option<Internals>,
     tags: Vec<ItemTag>,
+    focused: Option<Arc<Mutex<Element>>>,
+    tab_moved_focus: bool, //the char of a tab that moved focus is dropped
+    shortcut_fired: bool,  //so is the char of a shortcut
+    pressed: Option<Arc<Mutex<Element>>>, //target of the last button press
+    press: Option<Press>,
+    drag: Option<Drag>,
+    hovered_files: Vec<PathBuf>,
+    popups: Vec<Arc<Mutex<Menu>>>, //open menus, drawn above the content
+    popup_dismissed: bool,        //the release of a click that closed them is dropped
+    popup_escaped: bool,          //so is the focus loss of an escape that closed them
+    menu_focus: Option<Arc<Mutex<Element>>>, //focused before the menus opened
+    touch: Recognizer,
+    touches: Vec<(u64, Vec<ItemTag>)>, //tags under each touch point when it started
+    touch_origin: (properties::Position, Vec<ItemTag>), //of the first finger, gestures go there
+    touch_prevented: bool, //an element prevented the default of a touch, no gestures
+    fling: Option<Fling>,
+    dirty: bool, //something changed outside of the events, draw the next frame
+    laid_out: Option<u64>, //tree version of the last frame, the caches below hold for it
+    focus_order: RefCell<Option<(u64, Arc<Vec<Focusable>>)>>,
+    hovered: RefCell<Option<(u64, Vec<ItemTag>, Arc<EventPath>)>>, //path to the tags under the cursor
 }
 
+type EventPath = Vec<(Arc<Mutex<Element>>, Option<usize>)>;
+
 impl fmt::Debug for Window {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Window {{ name: {}, width: {}, height: {}, internals: {:?} }}", self.name, self.width, self.height, self.internals)
@@ -427,6 +661,26 @@ impl Window {
             id_generator,
             internals: None,
             tags: vec![],
+            focused: None,
+            tab_moved_focus: false,
+            shortcut_fired: false,
+            pressed: None,
+            press: None,
+            drag: None,
+            hovered_files: vec![],
+            popups: vec![],
+            popup_dismissed: false,
+            popup_escaped: false,
+            menu_focus: None,
+            touch: Recognizer::new(),
+            touches: vec![],
+            touch_origin: (properties::Position { x: 0.0, y: 0.0 }, vec![]),
+            touch_prevented: false,
+            fling: None,
+            dirty: true,
+            laid_out: None,
+            focus_order: RefCell::new(None),
+            hovered: RefCell::new(None),
         };
 
         _w.start_window();
@@ -438,6 +692,134 @@ impl Window {
         self.internals = Some(Internals::new(&self.name, self.width, self.height));
     }
 
+    //the tree version the caches can be kept for, none while a change has
+    //not been drawn yet and the ext ids are not assigned
+    fn cache_key(&self) -> Option<u64> {
+        let version = Manager::tree_version();
+        self.laid_out.filter(|v| *v == version)
+    }
+
+    //focusable elements in tab order: positive tab indices first, then tree order
+    fn get_focusable(&self) -> Arc<Vec<Focusable>> {
+        let key = self.cache_key();
+        if let Some((version, ref list)) = *self.focus_order.borrow() {
+            if key == Some(version) {
+                return list.clone();
+            }
+        }
+        let mut list = vec![];
+        collect_focusable(&self.root, &mut vec![], &mut list);
+        list.sort_by_key(|f| if f.tab_index > 0 { (0, f.tab_index) } else { (1, 0) });
+        let list = Arc::new(list);
+        if let Some(version) = key {
+            *self.focus_order.borrow_mut() = Some((version, list.clone()));
+        }
+        list
+    }
+
+    //the event path of the tags under the cursor
+    fn get_hovered_path(&self) -> Arc<EventPath> {
+        let key = self.cache_key();
+        if let Some((version, ref tags, ref path)) = *self.hovered.borrow() {
+            if key == Some(version) && *tags == self.tags {
+                return path.clone();
+            }
+        }
+        let path = Arc::new(self.get_event_path(&self.tags));
+        if let Some(version) = key {
+            *self.hovered.borrow_mut() = Some((version, self.tags.clone(), path.clone()));
+        }
+        path
+    }
+
+    pub fn get_focused(&self) -> Option<Arc<Mutex<Element>>> {
+        self.focused.clone()
+    }
+
+    //returns false if elem is not a focusable element of this window
+    pub fn focus(&mut self, elem: &Arc<Mutex<Element>>) -> bool {
+        let target = self
+            .get_focusable()
+            .iter()
+            .find(|f| Arc::ptr_eq(&f.element, elem))
+            .cloned();
+        match target {
+            Some(f) => {
+                self.set_focus(f);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn focus_next(&mut self, forward: bool) {
+        let list = self.get_focusable();
+        if list.is_empty() {
+            return;
+        }
+        let len = list.len();
+        let current = match self.focused {
+            Some(ref focused) => list.iter().position(|f| Arc::ptr_eq(&f.element, focused)),
+            None => None,
+        };
+        let next = match (current, forward) {
+            (Some(i), true) => (i + 1) % len,
+            (Some(i), false) => (i + len - 1) % len,
+            (None, true) => 0,
+            (None, false) => len - 1,
+        };
+        self.set_focus(list[next].clone());
+    }
+
+    pub fn clear_focus(&mut self) {
+        self.root
+            .lock()
+            .unwrap()
+            .on_primitive_event(&[], PrimitiveEvent::SetFocus(false));
+        self.focused = None;
+    }
+
+    //containers pass focus down the path and take it from everything else
+    fn set_focus(&mut self, f: Focusable) {
+        self.root
+            .lock()
+            .unwrap()
+            .on_primitive_event(&f.path, PrimitiveEvent::SetFocus(true));
+        self.focused = Some(f.element);
+    }
+
+    //whether elem is the root or any element below it, focusable or not
+    fn has_element(&self, elem: &Arc<Mutex<Element>>) -> bool {
+        if Arc::ptr_eq(&self.root, elem) {
+            return true;
+        }
+        let id = elem.lock().unwrap().get_ext_id();
+        let mut path = vec![];
+        find_below(&self.root, id, &mut path) && path.last().map_or(false, |e| Arc::ptr_eq(e, elem))
+    }
+
+    //whether the OS gives this window the keyboard
+    fn is_active(&self) -> bool {
+        self.internals.as_ref().map_or(false, |i| i.focused)
+    }
+
+    fn contains(&self, elem: &Arc<Mutex<Element>>) -> bool {
+        self.get_focusable()
+            .iter()
+            .any(|f| Arc::ptr_eq(&f.element, elem))
+    }
+
+    fn handle_focus_request(&mut self, request: &FocusRequest) {
+        match request {
+            FocusRequest::Element(elem) => {
+                self.focus(elem);
+            }
+            FocusRequest::Next => self.focus_next(true),
+            FocusRequest::Previous => self.focus_next(false),
+            FocusRequest::Clear => self.clear_focus(),
+        }
+    }
+
     fn get_tags(&mut self) -> (Vec<ItemTag>, Vec<ItemTag>) {
         let mut tags: Vec<ItemTag> = vec![];
 
@@ -446,17 +828,7 @@ impl Window {
 
         if let Some(ref mut i) = self.internals {
             if i.cursor_position.x > 0.0 && i.cursor_position.y > 0.0 {
-                let results = i.api.hit_test(
-                    i.document_id,
-                    None,
-                    i.cursor_position,
-                    HitTestFlags::FIND_ALL,
-                );
-                let mut ind = results.items.len();
-                while ind > 0 {
-                    ind -= 1;
-                    tags.push(results.items[ind].tag);
-                }
+                tags = i.hit_test(i.cursor_position);
             }
         }
 
@@ -488,11 +860,527 @@ impl Window {
         (new_tags, old_tags)
     }
 
+    //the cursor of the deepest hovered element that has one, a drag shows
+    //whether it can drop and an open menu the arrow
+    fn get_cursor(&self) -> properties::Cursor {
+        if let Some(drag) = self.drag.as_ref().filter(|d| d.source.is_some()) {
+            return if drag.accepted {
+                properties::Cursor::Move
+            } else {
+                properties::Cursor::NotAllowed
+            };
+        }
+        if let Some(ref i) = self.internals {
+            let p: properties::Position = i.cursor_position.into();
+            if self.popups.iter().any(|m| m.lock().unwrap().contains(&p)) {
+                return properties::Cursor::Default;
+            }
+        }
+        for (elm, _) in self.get_hovered_path().iter().rev() {
+            let elm = elm.lock().unwrap();
+            //disabled elements show the arrow
+            if elm.as_can_disable().map_or(false, |c| !c.get_enabled()) {
+                return properties::Cursor::Default;
+            }
+            let cursor = elm.get_properties().get_cursor();
+            if cursor != properties::Cursor::Default {
+                return cursor;
+            }
+        }
+        properties::Cursor::Default
+    }
+
+    fn focused_wants_tab(&self) -> bool {
+        match self.focused {
+            Some(ref f) => f.lock().unwrap().wants_tab(),
+            None => false,
+        }
+    }
+
+    //tags leading to the focused element, keyboard events are dispatched along them
+    fn get_focus_tags(&self) -> Vec<ItemTag> {
+        match self.focused {
+            Some(ref focused) => self
+                .get_focusable()
+                .iter()
+                .find(|f| Arc::ptr_eq(&f.element, focused))
+                .map_or(vec![], |f| f.path.clone()),
+            None => vec![],
+        }
+    }
+
+    //the elements along tags from the root down, each with the index of the
+    //tags it gets. Elements without tags of their own (wrappers) are included
+    //when they lead to a tagged element, but get no tags.
+    fn get_event_path(&self, tags: &[ItemTag]) -> Vec<(Arc<Mutex<Element>>, Option<usize>)> {
+        let mut best = vec![(self.root.clone(), None)];
+        find_path(&self.root, tags, None, &mut vec![], &mut best);
+        best
+    }
+
+    //capture handlers run from the root down to the target, then the target and
+    //its tagged ancestors act until one handles the event, and bubble handlers run
+    //back up. Ancestors get their own tags and the target the rest, without a
+    //match the root is the target
+    pub fn dispatch(&self, tags: &[ItemTag], e: PrimitiveEvent) -> (Arc<Mutex<Element>>, Event) {
+        let path = self.get_event_path(tags);
+        let last = path.len() - 1;
+        let target = path[last].0.clone();
+        let event = Event::new(e, target.lock().unwrap().get_ext_id());
+
+        for (i, (elm, _)) in path.iter().enumerate() {
+            if event.is_propagation_stopped() {
+                break;
+            }
+            event.set_phase(if i == last { EventPhase::Target } else { EventPhase::Capture });
+            elm.lock().unwrap().on_capture(&event);
+        }
+
+        let mut end = tags.len();
+        for (i, (elm, start)) in path.iter().enumerate().rev() {
+            let mut elm = elm.lock().unwrap();
+            let start = if i == last { Some(start.unwrap_or(0)) } else { *start };
+            if let Some(start) = start {
+                if !event.is_default_prevented()
+                    && !event.is_handled()
+                    && elm.on_primitive_event(&tags[start..end], event.get_primitive().clone())
+                {
+                    event.set_handled();
+                }
+                end = start;
+            }
+            if !event.is_propagation_stopped() {
+                event.set_phase(if i == last { EventPhase::Target } else { EventPhase::Bubble });
+                elm.on_bubble(&event);
+            }
+        }
+
+        (target, event)
+    }
+
+    //the callback bound to key in this window, unless the focused element keeps the key
+    fn find_shortcut(
+        &self,
+        key: VirtualKeyCode,
+        m: &properties::Modifiers,
+    ) -> Option<Arc<Mutex<Box<ShortcutFn>>>> {
+        if let Some(ref focused) = self.focused {
+            if focused.lock().unwrap().overrides_shortcut(key, m) {
+                return None;
+            }
+        }
+        SHORTCUTS
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|b| Arc::ptr_eq(&b.window, &self.root) && b.shortcut.matches(key, m))
+            .map(|b| b.callback.clone())
+    }
+
+    //once the cursor is far enough from a left press, the pressed element and
+    //then its ancestors are asked for something to drag
+    fn start_drag(&mut self, p: &properties::Position) -> bool {
+        let far = match self.press {
+            Some(ref press) => {
+                let (dx, dy) = (p.x - press.position.x, p.y - press.position.y);
+                dx * dx + dy * dy >= DRAG_THRESHOLD * DRAG_THRESHOLD
+            }
+            None => false,
+        };
+        if !far {
+            return false;
+        }
+        let press = self.press.take().unwrap();
+
+        let path = self.get_event_path(&press.tags);
+        let mut started = None;
+        for (elm, _) in path.iter().rev() {
+            let mut e = elm.lock().unwrap();
+            let event = DragStartEvent::new(press.position.clone());
+            e.exec_handler(ElementEvent::DragStart, &event);
+            if let Some(data) = event.take_data() {
+                started = Some((elm.clone(), data, e.get_bounds()));
+                break;
+            }
+        }
+        let (source, data, bounds) = match started {
+            Some(started) => started,
+            None => return false,
+        };
+
+        //the pressed element will not see the release, end its selection now
+        if let Some(pressed) = self.pressed.take() {
+            pressed.lock().unwrap().on_primitive_event(
+                &[],
+                PrimitiveEvent::Button(
+                    p.clone(),
+                    properties::Button::Left,
+                    properties::ButtonState::Released,
+                    press.modifiers,
+                    0,
+                ),
+            );
+        }
+
+        let offset = properties::Position {
+            x: (press.position.x - bounds.x).max(0.0).min(bounds.w),
+            y: (press.position.y - bounds.y).max(0.0).min(bounds.h),
+        };
+        self.drag = Some(Drag {
+            data,
+            source: Some(source),
+            offset,
+            size: (bounds.w, bounds.h),
+            position: p.clone(),
+            over: vec![],
+            accepted: false,
+        });
+        true
+    }
+
+    //enter and leave for the elements the cursor came to and left,
+    //then drag over from the element under the cursor up
+    fn drag_over(&mut self, p: &properties::Position, tags: &[ItemTag]) {
+        let path: Vec<Arc<Mutex<Element>>> =
+            self.get_event_path(tags).into_iter().map(|(e, _)| e).collect();
+        let drag = match self.drag {
+            Some(ref mut drag) => drag,
+            None => return,
+        };
+        drag.position = p.clone();
+        let event = DragEvent::new(p.clone(), drag.data.clone());
+
+        for old in drag.over.iter().rev() {
+            if !path.iter().any(|e| Arc::ptr_eq(e, old)) {
+                old.lock().unwrap().exec_handler(ElementEvent::DragLeave, &event);
+            }
+        }
+        for new in path.iter() {
+            if !drag.over.iter().any(|e| Arc::ptr_eq(e, new)) {
+                new.lock().unwrap().exec_handler(ElementEvent::DragEnter, &event);
+            }
+        }
+        for elm in path.iter().rev() {
+            if elm.lock().unwrap().exec_handler(ElementEvent::DragOver, &event) {
+                break;
+            }
+        }
+
+        drag.accepted = event.is_accepted();
+        drag.over = path;
+    }
+
+    //drops at `at`, or cancels the drag when it is None
+    fn end_drag(&mut self, at: Option<&properties::Position>) {
+        let drag = match self.drag.take() {
+            Some(drag) => drag,
+            None => return,
+        };
+
+        let mut dropped = false;
+        if let Some(p) = at {
+            let event = DragEvent::new(p.clone(), drag.data.clone());
+            for elm in drag.over.iter().rev() {
+                if elm.lock().unwrap().exec_handler(ElementEvent::Drop, &event) {
+                    dropped = true;
+                    break;
+                }
+            }
+        }
+
+        let event = DragEvent::new(drag.position.clone(), drag.data.clone());
+        for elm in drag.over.iter().rev() {
+            elm.lock().unwrap().exec_handler(ElementEvent::DragLeave, &event);
+        }
+
+        let end = DragEndEvent {
+            data: drag.data.clone(),
+            dropped,
+        };
+        if let Some(source) = drag.source {
+            source.lock().unwrap().exec_handler(ElementEvent::DragEnd, &end);
+        }
+    }
+
+    //files from the desktop are dragged over the elements as a Vec<PathBuf>
+    fn drag_files(&mut self, files: Vec<PathBuf>, p: &properties::Position, tags: &[ItemTag]) {
+        match self.drag {
+            Some(ref mut drag) => drag.data = DragData::new(files),
+            None => {
+                self.drag = Some(Drag {
+                    data: DragData::new(files),
+                    source: None,
+                    offset: p.clone(),
+                    size: (0.0, 0.0),
+                    position: p.clone(),
+                    over: vec![],
+                    accepted: false,
+                })
+            }
+        }
+        self.drag_over(p, tags);
+    }
+
+    //closes the menus that were open
+    pub fn show_menu(&mut self, menu: Arc<Mutex<Menu>>, at: properties::Position) {
+        if self.popups.is_empty() {
+            self.menu_focus = self.focused.clone();
+        }
+        self.close_menus();
+        menu.lock().unwrap().open_at(at);
+        self.press = None;
+        self.popups.push(menu);
+    }
+
+    pub fn close_menus(&mut self) {
+        for menu in self.popups.drain(..) {
+            menu.lock().unwrap().close();
+        }
+    }
+
+    //an open menu takes the pointer and the keyboard until it closes, a
+    //press outside closes it. Returns true if the event was taken.
+    fn popup_event(&mut self, e: &PrimitiveEvent) -> bool {
+        if self.popups.is_empty() {
+            match e {
+                PrimitiveEvent::Button(_, _, properties::ButtonState::Released, _, _) => {
+                    if self.popup_dismissed {
+                        self.popup_dismissed = false;
+                        return true;
+                    }
+                }
+                PrimitiveEvent::SetFocus(false) => {
+                    if self.popup_escaped {
+                        self.popup_escaped = false;
+                        return true;
+                    }
+                }
+                PrimitiveEvent::KeyInput(_, _, properties::ButtonState::Pressed, _) => {
+                    self.popup_escaped = false;
+                }
+                _ => (),
+            }
+            return false;
+        }
+        let top = self.popups[self.popups.len() - 1].clone();
+        match e {
+            PrimitiveEvent::Button(p, _, s, _, _) => {
+                //the element pressed before the menu opened still sees the release
+                if *s == properties::ButtonState::Released {
+                    if let Some(pressed) = self.pressed.take() {
+                        pressed.lock().unwrap().on_primitive_event(&[], e.clone());
+                    }
+                }
+                let over = self
+                    .popups
+                    .iter()
+                    .rev()
+                    .find(|m| m.lock().unwrap().contains(p))
+                    .cloned();
+                match over {
+                    Some(menu) => {
+                        menu.lock().unwrap().on_primitive_event(&[], e.clone());
+                    }
+                    None => {
+                        if *s == properties::ButtonState::Pressed {
+                            self.close_menus();
+                            self.popup_dismissed = true;
+                        }
+                    }
+                }
+            }
+            PrimitiveEvent::CursorMoved(_) | PrimitiveEvent::KeyInput(..) => {
+                if let PrimitiveEvent::KeyInput(Some(VirtualKeyCode::Escape), _, properties::ButtonState::Pressed, _) = e {
+                    self.popup_escaped = true;
+                }
+                top.lock().unwrap().on_primitive_event(&[], e.clone());
+            }
+            PrimitiveEvent::Char(_) | PrimitiveEvent::Wheel(..) | PrimitiveEvent::SetFocus(_) => (),
+            _ => return false,
+        }
+        self.popups.retain(|m| m.lock().unwrap().is_open());
+        //the element that had focus before the menus opened gets it back
+        if self.popups.is_empty() {
+            if let Some(f) = self.menu_focus.take() {
+                if !self.focused.as_ref().map_or(false, |x| Arc::ptr_eq(x, &f)) {
+                    self.focus(&f);
+                }
+            }
+        }
+        true
+    }
+
+    //the context menu of the target or of the nearest ancestor that has one
+    fn find_context_menu(&self, tags: &[ItemTag]) -> Option<Arc<Mutex<Menu>>> {
+        let menus = CONTEXT_MENUS.lock().unwrap();
+        self.get_event_path(tags).iter().rev().find_map(|(elm, _)| {
+            menus
+                .iter()
+                .find(|(owner, _)| Arc::ptr_eq(owner, elm))
+                .map(|(_, menu)| menu.clone())
+        })
+    }
+
+    //touch points go to the element they started on, then to the gesture
+    //recognizer
+    fn touch_event(
+        &mut self,
+        e: &PrimitiveEvent,
+        p: &properties::Position,
+        phase: &properties::TouchPhase,
+        id: u64,
+    ) {
+        if *phase == properties::TouchPhase::Started {
+            let tags = match self.internals {
+                Some(ref i) => i.hit_test(WorldPoint::new(p.x, p.y)),
+                None => vec![],
+            };
+            if self.touch.is_idle() {
+                self.fling = None;
+                self.touch_prevented = false;
+                self.touch_origin = (p.clone(), tags.clone());
+            }
+            self.touches.push((id, tags));
+        }
+        let tags = self
+            .touches
+            .iter()
+            .find(|t| t.0 == id)
+            .map_or(vec![], |t| t.1.clone());
+        let (_, event) = self.dispatch(&tags, e.clone());
+        if event.is_default_prevented() {
+            self.touch_prevented = true;
+        }
+        if *phase == properties::TouchPhase::Ended || *phase == properties::TouchPhase::Cancelled {
+            self.touches.retain(|t| t.0 != id);
+        }
+        let gestures = self.touch.touch(p, phase, id, Instant::now());
+        self.gestures(gestures);
+    }
+
+    //from the element under the first finger up to the first handler that
+    //returns true
+    fn gesture(&self, kind: ElementEvent, payload: &Any) -> bool {
+        self.get_event_path(&self.touch_origin.1)
+            .iter()
+            .rev()
+            .any(|(elm, _)| elm.lock().unwrap().exec_handler(kind.clone(), payload))
+    }
+
+    fn gestures(&mut self, gestures: Vec<Gesture>) {
+        if self.touch_prevented {
+            return;
+        }
+        for gesture in gestures.into_iter() {
+            match gesture {
+                Gesture::Tap(tap) => {
+                    if !self.gesture(ElementEvent::Tap, &tap) {
+                        self.touch_click(&tap.position, properties::Button::Left);
+                    }
+                }
+                Gesture::LongPress(press) => {
+                    if !self.gesture(ElementEvent::LongPress, &press) {
+                        self.touch_click(&press.position, properties::Button::Right);
+                    }
+                }
+                //the scroll frame under the finger follows a pan nobody
+                //handled, and flings on when it ends
+                Gesture::Pan(pan) => {
+                    if !self.gesture(ElementEvent::Pan, &pan) {
+                        let at = self.touch_origin.0.clone();
+                        if let Some(ref i) = self.internals {
+                            i.scroll(pan.delta.0, pan.delta.1, WorldPoint::new(at.x, at.y));
+                        }
+                        if pan.phase == GesturePhase::Ended {
+                            self.fling = Some(Fling::new(at, pan.velocity, Instant::now()));
+                        }
+                    }
+                }
+                Gesture::Pinch(pinch) => {
+                    self.gesture(ElementEvent::Pinch, &pinch);
+                }
+                Gesture::Swipe(swipe) => {
+                    self.gesture(ElementEvent::Swipe, &swipe);
+                }
+            }
+        }
+    }
+
+    //a tap or long press nobody handled clicks like the mouse would, so
+    //elements that only know the mouse work on touch screens too
+    fn touch_click(&mut self, p: &properties::Position, button: properties::Button) {
+        let tags = self.touch_origin.1.clone();
+        let m = properties::Modifiers {
+            shift: false,
+            ctrl: false,
+            alt: false,
+            logo: false,
+        };
+        let mut events = vec![PrimitiveEvent::Button(
+            p.clone(),
+            button.clone(),
+            properties::ButtonState::Pressed,
+            m.clone(),
+            1,
+        )];
+        if !tags.is_empty() && button == properties::Button::Left {
+            events.push(PrimitiveEvent::SetFocus(true));
+        }
+        events.push(PrimitiveEvent::Button(
+            p.clone(),
+            button,
+            properties::ButtonState::Released,
+            m,
+            1,
+        ));
+        self.action_events(events, &tags);
+    }
+
+    //long presses and flings go on between touch events
+    fn touch_tick(&mut self) {
+        let now = Instant::now();
+        let gestures = self.touch.update(now);
+        self.gestures(gestures);
+
+        let stopped = match self.fling {
+            Some(ref mut fling) => {
+                let (dx, dy) = fling.step(now);
+                if let Some(ref i) = self.internals {
+                    i.scroll(dx, dy, WorldPoint::new(fling.at.x, fling.at.y));
+                }
+                fling.is_stopped()
+            }
+            None => false,
+        };
+        if stopped {
+            self.fling = None;
+        }
+    }
+
     fn action_events(&mut self, events: Vec<PrimitiveEvent>, tags: &Vec<ItemTag>) {
+        //a drop of several files comes as one event per file
+        let mut dropped: Option<(properties::Position, Vec<PathBuf>)> = None;
         for e in events.iter() {
             /*if exit {
                 return true;
             }*/
+            if self.popup_event(e) {
+                continue;
+            }
+            //a key bound to a shortcut runs it, escape first ends a drag
+            if let PrimitiveEvent::KeyInput(Some(key), _, properties::ButtonState::Pressed, m) = e {
+                if self.drag.is_none() || *key != VirtualKeyCode::Escape {
+                    //the callback is cloned out of the registry, so it can add
+                    //and remove shortcuts
+                    if let Some(callback) = self.find_shortcut(*key, m) {
+                        if let Ok(mut f) = callback.lock() {
+                            (*f)();
+                        }
+                        self.shortcut_fired = true;
+                        continue;
+                    }
+                }
+            }
             match e {
                 /*PrimitiveEvent::Exit => {
                     //exit = true;
@@ -507,40 +1395,157 @@ impl Window {
                 PrimitiveEvent::SetFocus(b) => {
                     if !*b {
                         self.root.lock().unwrap().on_primitive_event(&[], e.clone());
+                        self.focused = None;
                     } else {
                         self.root
                             .lock()
                             .unwrap()
                             .on_primitive_event(&tags, e.clone());
+                        //the deepest focusable element that was clicked
+                        self.focused = self
+                            .get_focusable()
+                            .iter()
+                            .filter(|f| {
+                                f.path.last().map_or(false, |p| tags.iter().any(|t| t.0 == p.0))
+                            })
+                            .max_by_key(|f| f.path.len())
+                            .map(|f| f.element.clone());
+                    }
+                }
+                PrimitiveEvent::Button(p, b, s, m, _) => {
+                    //a drag takes the button until it is released
+                    if self.drag.is_some() {
+                        if *b == properties::Button::Left && *s == properties::ButtonState::Released {
+                            self.end_drag(Some(p));
+                        }
+                        continue;
+                    }
+                    let (target, event) = self.dispatch(&tags, e.clone());
+                    match s {
+                        properties::ButtonState::Pressed => {
+                            self.pressed = Some(target);
+                            if *b == properties::Button::Left {
+                                self.press = Some(Press {
+                                    position: p.clone(),
+                                    tags: tags.clone(),
+                                    modifiers: m.clone(),
+                                });
+                            }
+                            //handlers can prevent the default to keep the context menu closed
+                            if *b == properties::Button::Right && !event.is_default_prevented() {
+                                if let Some(menu) = self.find_context_menu(&tags) {
+                                    self.show_menu(menu, p.clone());
+                                }
+                            }
+                        }
+                        properties::ButtonState::Released => {
+                            self.press = None;
+                            //the pressed element sees the release even when the
+                            //cursor has left it, so it can end drags
+                            if let Some(pressed) = self.pressed.take() {
+                                if !Arc::ptr_eq(&pressed, &target) {
+                                    pressed.lock().unwrap().on_primitive_event(&[], e.clone());
+                                }
+                            }
+                        }
                     }
                 }
-                PrimitiveEvent::Button(_, _, _, _) => {
-                    self.root
-                        .lock()
-                        .unwrap()
-                        .on_primitive_event(&tags, e.clone());
+                PrimitiveEvent::Char(c) => {
+                    if *c == '\t' && self.tab_moved_focus {
+                        self.tab_moved_focus = false;
+                        continue;
+                    }
+                    if self.shortcut_fired {
+                        self.shortcut_fired = false;
+                        continue;
+                    }
+                    let focus_tags = self.get_focus_tags();
+                    self.dispatch(&focus_tags, e.clone());
                 }
-                PrimitiveEvent::Char(_) => {
-                    self.root
-                        .lock()
-                        .unwrap()
-                        .on_primitive_event(&tags, e.clone());
+                PrimitiveEvent::CursorMoved(p) => {
+                    if self.drag.is_some() || self.start_drag(p) {
+                        self.drag_over(p, &tags);
+                        continue;
+                    }
+                    let (target, _) = self.dispatch(&tags, e.clone());
+                    //the pressed element follows the cursor outside of it, e.g. to select text
+                    if let Some(ref pressed) = self.pressed {
+                        if !Arc::ptr_eq(pressed, &target) {
+                            pressed.lock().unwrap().on_primitive_event(&[], e.clone());
+                        }
+                    }
                 }
-                PrimitiveEvent::CursorMoved(_) => {
-                    self.root
-                        .lock()
-                        .unwrap()
-                        .on_primitive_event(&tags, e.clone());
+                PrimitiveEvent::Wheel(_, delta, m) => {
+                    //the hovered element gets the wheel before the scroll frames
+                    let (_, event) = self.dispatch(&tags, e.clone());
+                    if !event.is_handled() && !event.is_default_prevented() {
+                        let (mut dx, mut dy) = delta.to_pixels(Manager::get_wheel_line_height());
+                        //shift turns a vertical wheel sideways, as in the textbox
+                        if m.shift && dx == 0.0 {
+                            dx = dy;
+                            dy = 0.0;
+                        }
+                        if let Some(ref i) = self.internals {
+                            i.scroll(dx, dy, i.cursor_position);
+                        }
+                    }
                 }
-                PrimitiveEvent::KeyInput(_, _, _, _) => {
-                    self.root
-                        .lock()
-                        .unwrap()
-                        .on_primitive_event(&tags, e.clone());
+                PrimitiveEvent::Touch(p, phase, id) => {
+                    self.touch_event(e, p, phase, *id);
+                }
+                PrimitiveEvent::FileHovered(p, path) => {
+                    self.hovered_files.push(path.clone());
+                    let files = self.hovered_files.clone();
+                    self.drag_files(files, p, &tags);
+                }
+                PrimitiveEvent::FileDropped(p, path) => {
+                    let drop = dropped.get_or_insert_with(|| (p.clone(), vec![]));
+                    drop.1.push(path.clone());
+                }
+                PrimitiveEvent::FileHoverCancelled => {
+                    self.hovered_files.clear();
+                    if self.drag.as_ref().map_or(false, |d| d.source.is_none()) {
+                        self.end_drag(None);
+                    }
+                }
+                PrimitiveEvent::KeyInput(Some(VirtualKeyCode::Escape), _, properties::ButtonState::Pressed, _)
+                    if self.drag.is_some() =>
+                {
+                    self.end_drag(None);
+                }
+                PrimitiveEvent::KeyInput(Some(key), _, properties::ButtonState::Pressed, m)
+                    if *key == VirtualKeyCode::Apps || (*key == VirtualKeyCode::F10 && m.shift) =>
+                {
+                    //the context menu key opens the menu of the focused element below it
+                    let focus_tags = self.get_focus_tags();
+                    if let (Some(menu), Some(focused)) = (self.find_context_menu(&focus_tags), self.get_focused()) {
+                        let b = focused.lock().unwrap().get_bounds();
+                        self.show_menu(menu, properties::Position { x: b.x, y: b.y + b.h });
+                    }
+                    self.shortcut_fired = false;
+                }
+                PrimitiveEvent::KeyInput(Some(VirtualKeyCode::Tab), _, properties::ButtonState::Pressed, m)
+                    if !m.ctrl && !m.alt && !self.focused_wants_tab() =>
+                {
+                    self.focus_next(!m.shift);
+                    self.tab_moved_focus = true;
+                    self.shortcut_fired = false;
+                }
+                PrimitiveEvent::KeyInput(_, _, s, _) => {
+                    if *s == properties::ButtonState::Pressed {
+                        self.shortcut_fired = false;
+                    }
+                    let focus_tags = self.get_focus_tags();
+                    self.dispatch(&focus_tags, e.clone());
                 }
                 _ => (),
             }
         }
+        if let Some((p, files)) = dropped {
+            self.hovered_files.clear();
+            self.drag_files(files, &p, &tags);
+            self.end_drag(Some(&p));
+        }
     }
 
     pub fn tick(&mut self) -> bool {
@@ -583,7 +1588,24 @@ impl Window {
         //    println!("{:?}", events);
         //}
 
+        let had_events = !events.is_empty();
         self.action_events(events, &tags);
+        self.touch_tick();
+
+        let cursor = self.get_cursor();
+        if let Some(ref mut i) = self.internals {
+            i.set_cursor(cursor);
+        }
+
+        //on demand, only windows that got input, are animating or were
+        //invalidated are drawn again
+        let moving = self.fling.is_some() || self.drag.is_some() || !self.touches.is_empty();
+        //a changed tree is drawn so it gets its ext ids
+        let changed = self.laid_out != Some(Manager::tree_version());
+        if *REDRAW_ON_DEMAND.lock().unwrap() && !self.dirty && !had_events && !moving && !changed {
+            return exit;
+        }
+        self.dirty = false;
 
         let mut window: Option<glutin::WindowedContext<glutin::NotCurrent>> = None;
 
@@ -628,7 +1650,10 @@ impl Window {
         let framebuffer_size = framebuffer_size.unwrap();
         let layout_size = layout_size.unwrap();
 
+        let version = Manager::tree_version();
         self.render_root(&api, &mut builder, font_store, dpi as f32);
+        font_store.end_frame();
+        self.laid_out = Some(version);
 
         if let Some(ref mut i) = self.internals {
             txn.set_window_parameters(
@@ -696,12 +1721,51 @@ impl Window {
             &mut gen,
         );
 
+        let extent = properties::Extent {
+            x: 0.0,
+            y: 0.0,
+            w: self.width as f32,
+            h: self.height as f32,
+            dpi,
+        };
+        for menu in self.popups.iter() {
+            menu.lock()
+                .unwrap()
+                .render(api, builder, extent.clone(), font_store, None, &mut gen);
+        }
+
+        //the drag image follows the cursor, it is not tagged so hit-testing
+        //finds what is under it
+        if let Some(drag) = self.drag.as_ref().filter(|d| d.source.is_some()) {
+            let color = if drag.accepted {
+                ColorF::new(0.2, 0.5, 1.0, 0.4)
+            } else {
+                ColorF::new(0.5, 0.5, 0.5, 0.4)
+            };
+            builder.push_rect(
+                &LayoutPrimitiveInfo::new(
+                    (drag.position.x - drag.offset.x, drag.position.y - drag.offset.y)
+                        .by(drag.size.0, drag.size.1),
+                ),
+                color,
+            );
+        }
+
         builder.pop_stacking_context();
     }
 }
 
 impl Drop for Window {
     fn drop(&mut self) {
+        SHORTCUTS
+            .lock()
+            .unwrap()
+            .retain(|b| !Arc::ptr_eq(&b.window, &self.root));
+        CONTEXT_MENUS
+            .lock()
+            .unwrap()
+            .retain(|(owner, _)| !self.has_element(owner));
+        self.close_menus();
         let mut x = None;
         mem::swap(&mut x, &mut self.internals);
         let x = x.unwrap();
@@ -709,9 +1773,129 @@ impl Drop for Window {
     }
 }
 
+pub type TimerFn = FnMut() + Send;
+
+struct Timer {
+    id: u64,
+    due: Instant,
+    delay: Duration,
+    repeat: Option<Duration>,
+    callback: Box<TimerFn>,
+}
+
+#[derive(Default)]
+struct Timers {
+    next_id: u64,
+    timers: Vec<Timer>,
+    running: Vec<u64>,
+    cancelled: Vec<u64>, //cleared while their callback was running
+}
+
+pub type ShortcutFn = FnMut() + Send;
+
+//e.g. Shortcut::new(VirtualKeyCode::P).ctrl().shift()
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shortcut {
+    pub key: VirtualKeyCode,
+    pub modifiers: properties::Modifiers,
+}
+
+impl Shortcut {
+    pub fn new(key: VirtualKeyCode) -> Shortcut {
+        Shortcut {
+            key,
+            modifiers: properties::Modifiers {
+                shift: false,
+                ctrl: false,
+                alt: false,
+                logo: false,
+            },
+        }
+    }
+
+    pub fn ctrl(mut self) -> Shortcut {
+        self.modifiers.ctrl = true;
+        self
+    }
+
+    pub fn shift(mut self) -> Shortcut {
+        self.modifiers.shift = true;
+        self
+    }
+
+    pub fn alt(mut self) -> Shortcut {
+        self.modifiers.alt = true;
+        self
+    }
+
+    pub fn logo(mut self) -> Shortcut {
+        self.modifiers.logo = true;
+        self
+    }
+
+    pub fn matches(&self, key: VirtualKeyCode, modifiers: &properties::Modifiers) -> bool {
+        self.key == key && self.modifiers == *modifiers
+    }
+}
+
+impl fmt::Display for Shortcut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.modifiers.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.modifiers.shift {
+            write!(f, "Shift+")?;
+        }
+        if self.modifiers.alt {
+            write!(f, "Alt+")?;
+        }
+        if self.modifiers.logo {
+            write!(f, "Super+")?;
+        }
+        //digits are Key1, Key2...
+        let key = format!("{:?}", self.key);
+        write!(f, "{}", key.trim_start_matches("Key"))
+    }
+}
+
+//the window already binds the shortcut, with the existing description
+#[derive(Debug, Clone)]
+pub struct ShortcutConflict {
+    pub shortcut: Shortcut,
+    pub description: String,
+}
+
+impl fmt::Display for ShortcutConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} is already bound to '{}'", self.shortcut, self.description)
+    }
+}
+
+struct Binding {
+    window: Arc<Mutex<Element>>, //root element of the window
+    shortcut: Shortcut,
+    description: String,
+    callback: Arc<Mutex<Box<ShortcutFn>>>,
+}
+
 lazy_static! {
+    static ref SHORTCUTS: Mutex<Vec<Binding>> = Mutex::new(vec![]);
+    static ref TIMERS: Mutex<Timers> = Mutex::new(Timers::default());
+    static ref FOCUS_REQUESTS: Mutex<Vec<FocusRequest>> = Mutex::new(vec![]);
+    static ref CONTEXT_MENUS: Mutex<Vec<(Arc<Mutex<Element>>, Arc<Mutex<Menu>>)>> = Mutex::new(vec![]);
+    static ref MENU_REQUESTS: Mutex<Vec<(Arc<Mutex<Menu>>, properties::Position, Arc<Mutex<Element>>)>> =
+        Mutex::new(vec![]);
     static ref TOADD: Mutex<Vec<(Arc<Mutex<Element>>, String, f64, f64)>> = Mutex::new(vec![]);
     static ref TODEL: Mutex<Vec<glutin::WindowId>> = Mutex::new(vec![]);
+    //longest time between and farthest distance apart of the presses of a multi click
+    static ref CLICK_SETTINGS: Mutex<(Duration, f32)> = Mutex::new((Duration::from_millis(500), 4.0));
+    //pixels scrolled for a line of a wheel that scrolls by lines
+    static ref WHEEL_LINE_HEIGHT: Mutex<f32> = Mutex::new(38.0);
+    //elements that changed outside of the events, their windows are drawn again
+    static ref INVALIDATED: Mutex<Vec<Arc<Mutex<Element>>>> = Mutex::new(vec![]);
+    static ref REDRAW_ON_DEMAND: Mutex<bool> = Mutex::new(false);
+    //bumped when elements are added, removed or change whether and in which order they take focus
+    static ref TREE_VERSION: Mutex<u64> = Mutex::new(0);
 }
 
 pub struct Manager {
@@ -740,6 +1924,41 @@ impl Manager {
             let mut wmo = Manager::get();
             if let Some(ref mut _wmo) = wmo {
                 if let Ok(ref mut wm) = _wmo.lock() {
+                    //a timer can change any element, so every window is drawn again
+                    if Manager::run_timers() {
+                        for w in wm.windows.iter_mut() {
+                            w.dirty = true;
+                        }
+                    }
+                    let invalidated: Vec<_> = INVALIDATED.lock().unwrap().drain(..).collect();
+                    for elem in invalidated.iter() {
+                        if let Some(w) = wm.windows.iter_mut().find(|w| w.has_element(elem)) {
+                            w.dirty = true;
+                        }
+                    }
+                    //focus changes asked for since the last frame
+                    let requests: Vec<FocusRequest> = FOCUS_REQUESTS.lock().unwrap().drain(..).collect();
+                    for request in requests.iter() {
+                        //tab order and clearing go to the window the user is in
+                        let target = match request {
+                            FocusRequest::Element(elem) => wm.windows.iter().position(|w| w.contains(elem)),
+                            _ => wm
+                                .windows
+                                .iter()
+                                .position(|w| w.is_active())
+                                .or(if wm.windows.len() == 1 { Some(0) } else { None }),
+                        };
+                        if let Some(i) = target {
+                            wm.windows[i].handle_focus_request(request);
+                        }
+                    }
+                    //so are the menus to show
+                    let requests: Vec<_> = MENU_REQUESTS.lock().unwrap().drain(..).collect();
+                    for (menu, at, owner) in requests.into_iter() {
+                        if let Some(w) = wm.windows.iter_mut().find(|w| w.has_element(&owner)) {
+                            w.show_menu(menu, at);
+                        }
+                    }
                     //add the windows to be added
                     if let Ok(ref mut to_add) = TOADD.lock() {
                         loop {
@@ -763,9 +1982,8 @@ impl Manager {
                             println!("Drop window ID {:?}, thread ID: {:?}", wid, thread::current().id());
 
                             for i in 0..wm.windows.len(){
-                                if let Some(ref mut internal) = wm.windows[i].internals {
+                                if let Some(ref internal) = wm.windows[i].internals {
                                     if wid == internal.get_window_id().unwrap() {
-                                        internal.api.shut_down();
                                         let x = wm.windows.remove(i);
                                         drop(x);
                                         println!("Window ID dropped {:?}, thread ID: {:?}", wid, thread::current().id());
@@ -776,6 +1994,9 @@ impl Manager {
                         }
                         to_del.clear();
                     }
+                    //every window has ended its frame, so fonts none of them
+                    //uses any more can go
+                    font::purge_fonts();
                     //if all windows done, then exit the app
                     if wm.windows.is_empty() {
                         return;
@@ -794,6 +2015,199 @@ impl Manager {
         }
     }
 
+    //presses at most time apart and within distance pixels count as double and triple clicks
+    pub fn set_multi_click(time: Duration, distance: f32) {
+        if let Ok(ref mut settings) = CLICK_SETTINGS.lock() {
+            **settings = (time, distance);
+        }
+    }
+
+    //pixels scrolled for a line of a wheel that scrolls by lines
+    pub fn set_wheel_line_height(height: f32) {
+        if let Ok(ref mut line_height) = WHEEL_LINE_HEIGHT.lock() {
+            **line_height = height;
+        }
+    }
+
+    pub fn get_wheel_line_height() -> f32 {
+        *WHEEL_LINE_HEIGHT.lock().unwrap()
+    }
+
+    fn add_timer(delay: Duration, repeat: Option<Duration>, callback: Box<TimerFn>) -> u64 {
+        let mut timers = TIMERS.lock().unwrap();
+        timers.next_id += 1;
+        let id = timers.next_id;
+        timers.timers.push(Timer {
+            id,
+            due: Instant::now() + delay,
+            delay,
+            repeat,
+            callback,
+        });
+        id
+    }
+
+    //runs f once on the UI thread after delay, returns an id for clear_timer
+    pub fn set_timeout<F: FnMut() + Send + 'static>(delay: Duration, f: F) -> u64 {
+        Manager::add_timer(delay, None, Box::new(f))
+    }
+
+    pub fn set_interval<F: FnMut() + Send + 'static>(interval: Duration, f: F) -> u64 {
+        Manager::add_timer(interval, Some(interval), Box::new(f))
+    }
+
+    pub fn clear_timer(id: u64) {
+        let mut timers = TIMERS.lock().unwrap();
+        let count = timers.timers.len();
+        timers.timers.retain(|t| t.id != id);
+        if timers.timers.len() == count && timers.running.contains(&id) {
+            timers.cancelled.push(id);
+        }
+    }
+
+    //puts a pending timer back to its full delay, returns false if it is
+    //not waiting, e.g. a timeout that already ran
+    pub fn restart_timer(id: u64) -> bool {
+        let mut timers = TIMERS.lock().unwrap();
+        if let Some(t) = timers.timers.iter_mut().find(|t| t.id == id) {
+            t.due = Instant::now() + t.delay;
+            return true;
+        }
+        false
+    }
+
+    //marks an element as changed, so its window is drawn again
+    pub fn invalidate(elem: &Arc<Mutex<Element>>) {
+        INVALIDATED.lock().unwrap().push(elem.clone());
+    }
+
+    //call after changing the children of an element or whether it can take
+    //focus, windows keep the focus order and the hovered elements until then
+    pub fn tree_changed() {
+        *TREE_VERSION.lock().unwrap() += 1;
+    }
+
+    fn tree_version() -> u64 {
+        *TREE_VERSION.lock().unwrap()
+    }
+
+    //when on, windows are only drawn after input, animations, timers and
+    //invalidate instead of every frame
+    pub fn set_redraw_on_demand(on: bool) {
+        *REDRAW_ON_DEMAND.lock().unwrap() = on;
+    }
+
+    //the timers are taken out of the lock while they run, so the callbacks
+    //can add and clear timers. Returns whether any ran.
+    fn run_timers() -> bool {
+        let now = Instant::now();
+        let due: Vec<Timer> = {
+            let mut timers = TIMERS.lock().unwrap();
+            let (due, pending): (Vec<Timer>, Vec<Timer>) =
+                timers.timers.drain(..).partition(|t| t.due <= now);
+            timers.timers = pending;
+            timers.running = due.iter().map(|t| t.id).collect();
+            due
+        };
+        if due.is_empty() {
+            return false;
+        }
+
+        let mut again = vec![];
+        for mut timer in due {
+            (timer.callback)();
+            if let Some(repeat) = timer.repeat {
+                timer.due = now + repeat;
+                again.push(timer);
+            }
+        }
+
+        let mut timers = TIMERS.lock().unwrap();
+        let cancelled = mem::replace(&mut timers.cancelled, vec![]);
+        timers.running.clear();
+        again.retain(|t| !cancelled.contains(&t.id));
+        timers.timers.append(&mut again);
+        true
+    }
+
+    //the callback runs whichever element has focus, unless it overrides the key
+    pub fn add_shortcut<F: FnMut() + Send + 'static>(
+        window: &Arc<Mutex<Element>>,
+        shortcut: Shortcut,
+        description: &str,
+        f: F,
+    ) -> Result<(), ShortcutConflict> {
+        let mut shortcuts = SHORTCUTS.lock().unwrap();
+        let existing = shortcuts
+            .iter()
+            .find(|b| Arc::ptr_eq(&b.window, window) && b.shortcut == shortcut);
+        if let Some(b) = existing {
+            return Err(ShortcutConflict {
+                shortcut,
+                description: b.description.clone(),
+            });
+        }
+        let callback: Box<ShortcutFn> = Box::new(f);
+        shortcuts.push(Binding {
+            window: window.clone(),
+            shortcut,
+            description: description.to_owned(),
+            callback: Arc::new(Mutex::new(callback)),
+        });
+        Ok(())
+    }
+
+    //false if the shortcut was not bound
+    pub fn remove_shortcut(window: &Arc<Mutex<Element>>, shortcut: &Shortcut) -> bool {
+        let mut shortcuts = SHORTCUTS.lock().unwrap();
+        let count = shortcuts.len();
+        shortcuts.retain(|b| !(Arc::ptr_eq(&b.window, window) && b.shortcut == *shortcut));
+        shortcuts.len() != count
+    }
+
+    //in the order they were added, e.g. for a help screen
+    pub fn get_shortcuts(window: &Arc<Mutex<Element>>) -> Vec<(Shortcut, String)> {
+        SHORTCUTS
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|b| Arc::ptr_eq(&b.window, window))
+            .map(|b| (b.shortcut.clone(), b.description.clone()))
+            .collect()
+    }
+
+    //applied before the next frame, so handlers can call it
+    pub fn focus(elem: Arc<Mutex<Element>>) {
+        FOCUS_REQUESTS.lock().unwrap().push(FocusRequest::Element(elem));
+    }
+
+    pub fn focus_next() {
+        FOCUS_REQUESTS.lock().unwrap().push(FocusRequest::Next);
+    }
+
+    pub fn focus_previous() {
+        FOCUS_REQUESTS.lock().unwrap().push(FocusRequest::Previous);
+    }
+
+    pub fn clear_focus() {
+        FOCUS_REQUESTS.lock().unwrap().push(FocusRequest::Clear);
+    }
+
+    //shown when elem or an element below it without a menu of its own is right
+    //clicked, unless the press is prevented. None removes the menu
+    pub fn set_context_menu(elem: &Arc<Mutex<Element>>, menu: Option<Arc<Mutex<Menu>>>) {
+        let mut menus = CONTEXT_MENUS.lock().unwrap();
+        menus.retain(|(owner, _)| !Arc::ptr_eq(owner, elem));
+        if let Some(menu) = menu {
+            menus.push((elem.clone(), menu));
+        }
+    }
+
+    //in the window that contains owner, shown before the next frame
+    pub fn show_menu(menu: Arc<Mutex<Menu>>, at: properties::Position, owner: Arc<Mutex<Element>>) {
+        MENU_REQUESTS.lock().unwrap().push((menu, at, owner));
+    }
+
     pub fn add(elem: Arc<Mutex<Element>>, name: String, width: f64, height: f64) {
         if let Ok(ref mut to_add) = TOADD.lock() {
             to_add.push((elem, name, width, height));
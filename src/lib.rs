@@ -12,6 +12,8 @@ extern crate lazy_static;
 extern crate harfbuzz_sys;
 extern crate itertools;
 extern crate unicode_bidi;
+extern crate unicode_segmentation;
+extern crate xi_unicode;
 
 pub mod data;
 pub mod elements;